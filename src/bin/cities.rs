@@ -0,0 +1,253 @@
+/// Command line tool for asking questions about the city data.
+///
+/// > cargo run --bin cities -- query "country=US and admin1=CA and population>1000000"
+/// > cargo run --bin cities -- largest --by country
+/// > cargo run --bin cities -- --data cities15000.txt --format json nearest 59.33 18.07
+/// > cargo run --bin cities -- within 59.33 18.07 200
+/// > cargo run --bin cities -- map --output sweden.svg "country=SE order by population desc limit 20"
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use apricity::Coordinate;
+use rustdemo::dataset::default_cities_path;
+use rustdemo::geo::load_countries;
+use rustdemo::geo::report::{MapReport, Marker};
+use rustdemo::group::{self, Groups};
+use rustdemo::output::{write_cities, OutputFormat};
+use rustdemo::{CityData, CityDataset, CityQuery, SpatialIndex};
+
+const USAGE: &str = "\
+Usage: cities [--data <path>] [--format <name>] <command>
+
+Commands:
+  query <query>                          Cities matching a query, e.g.
+                                         \"country=SE order by population desc limit 5\"
+  largest [--by country|admin1|timezone] Largest city in each country, region or timezone
+  stats [<query>]                        Count, population and elevation of the matching cities
+  nearest <latitude> <longitude> [--count <n>]
+                                         Cities closest to a coordinate
+  within <latitude> <longitude> <km>     Cities within a distance of a coordinate, closest first
+  export [--output <path>] [<query>]     Write the matching cities, or all of them, to a file
+  map [--output <path>] [<query>]        Draw the matching cities on a world map, as PNG or,
+                                         if the path ends in .svg, SVG (default: map.png)
+
+Options:
+  --data <path>    City data to load, JSON or GeoNames .txt (default: $CITIES_PATH or cities100k.json)
+  --format <name>  Output format of the commands that list cities: table, csv, json, jsonl or
+                   geojson (default: table, json for export)
+";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GroupBy {
+    Country,
+    Admin1,
+    Timezone,
+}
+
+enum Command {
+    Query(CityQuery),
+    Largest(GroupBy),
+    Stats(CityQuery),
+    Nearest { coordinate: Coordinate, count: usize },
+    Within { coordinate: Coordinate, radius_km: f64 },
+    Export { output: Option<PathBuf>, query: CityQuery },
+    Map { output: PathBuf, query: CityQuery, title: String },
+}
+
+struct Options {
+    data: PathBuf,
+    /// `None` when not given, which is a table, except for `export` which writes JSON.
+    format: Option<OutputFormat>,
+    command: Command,
+}
+
+fn parse_arguments(arguments: Vec<String>) -> Result<Options, String> {
+    let mut data = default_cities_path();
+    let mut format = None;
+    let mut output = None;
+    let mut group_by = GroupBy::Country;
+    let mut count = 10;
+    let mut positional = Vec::new();
+    // Options other than --data, to check that the command uses them
+    let mut given = Vec::new();
+
+    let mut arguments = arguments.into_iter();
+    while let Some(argument) = arguments.next() {
+        let mut value = |name: &str| arguments.next().ok_or(format!("{} needs a value", name));
+        if argument.starts_with("--") && argument != "--data" {
+            given.push(argument.clone());
+        }
+        match argument.as_str() {
+            "--data" => data = PathBuf::from(value("--data")?),
+            "--output" => output = Some(PathBuf::from(value("--output")?)),
+            "--format" => format = Some(value("--format")?.parse()?),
+            "--by" => {
+                group_by = match value("--by")?.as_str() {
+                    "country" => GroupBy::Country,
+                    "admin1" => GroupBy::Admin1,
+                    "timezone" => GroupBy::Timezone,
+                    other => return Err(format!("can't group by {:?}", other)),
+                }
+            }
+            "--count" => {
+                count = value("--count")?
+                    .parse()
+                    .map_err(|_| "--count needs a number".to_string())?
+            }
+            option if option.starts_with("--") => return Err(format!("unknown option {:?}", option)),
+            _ => positional.push(argument),
+        }
+    }
+
+    let parse_query = |words: &[String]| -> Result<CityQuery, String> {
+        words.join(" ").parse::<CityQuery>().map_err(|e| e.to_string())
+    };
+    let number = |x: &String| x.parse::<f64>().map_err(|_| format!("{:?} is not a number", x));
+    let command = match positional.split_first() {
+        Some((command, rest)) => match (command.as_str(), rest) {
+            ("query", rest) => Command::Query(parse_query(rest)?),
+            ("largest", []) => Command::Largest(group_by),
+            ("stats", rest) => Command::Stats(parse_query(rest)?),
+            ("nearest", [latitude, longitude]) => {
+                let coordinate = Coordinate {
+                    latitude: number(latitude)?,
+                    longitude: number(longitude)?,
+                };
+                Command::Nearest { coordinate, count }
+            }
+            ("within", [latitude, longitude, radius_km]) => {
+                let coordinate = Coordinate {
+                    latitude: number(latitude)?,
+                    longitude: number(longitude)?,
+                };
+                Command::Within {
+                    coordinate,
+                    radius_km: number(radius_km)?,
+                }
+            }
+            ("export", rest) => Command::Export {
+                output,
+                query: parse_query(rest)?,
+            },
+            ("map", rest) => Command::Map {
+                output: output.take().unwrap_or_else(|| PathBuf::from("map.png")),
+                query: parse_query(rest)?,
+                title: rest.join(" "),
+            },
+            (command, _) => return Err(format!("unknown command or arguments for {:?}", command)),
+        },
+        None => return Err("no command given".to_string()),
+    };
+
+    let used: &[&str] = match command {
+        Command::Query(_) | Command::Within { .. } => &["--format"],
+        Command::Largest(_) => &["--by", "--format"],
+        Command::Stats(_) => &[],
+        Command::Nearest { .. } => &["--count", "--format"],
+        Command::Export { .. } => &["--output", "--format"],
+        Command::Map { .. } => &["--output"],
+    };
+    if let Some(option) = given.iter().find(|x| !used.contains(&x.as_str())) {
+        return Err(format!("{} can't be used with {:?}", option, positional[0]));
+    }
+
+    Ok(Options {
+        data,
+        format,
+        command,
+    })
+}
+
+fn largest(cities: &[CityData], group_by: GroupBy) -> Vec<&CityData> {
+    fn largest_by<K: Ord>(cities: &[CityData], key: impl Fn(&CityData) -> K) -> Vec<&CityData> {
+        let groups = Groups::new(cities, key);
+        groups.largest().into_iter().map(|(_, city)| city).collect()
+    }
+    let mut result = match group_by {
+        GroupBy::Country => largest_by(cities, group::country),
+        GroupBy::Admin1 => largest_by(cities, group::admin1),
+        GroupBy::Timezone => largest_by(cities, group::timezone),
+    };
+    result.sort_by_key(|x| std::cmp::Reverse(x.population));
+    result
+}
+
+fn print_stats(out: &mut dyn Write, cities: &[&CityData]) -> std::io::Result<()> {
+    let count = cities.len();
+    let total_population = cities.iter().map(|x| x.population).sum::<i64>();
+    let countries = cities.iter().filter_map(|x| x.country_code).collect::<HashSet<_>>();
+    writeln!(out, "Cities:           {}", count)?;
+    writeln!(out, "Countries:        {}", countries.len())?;
+    writeln!(out, "Total population: {}", total_population)?;
+    if count > 0 {
+        let largest = cities.iter().max_by_key(|x| x.population).unwrap();
+        let smallest = cities.iter().min_by_key(|x| x.population).unwrap();
+        let highest = cities.iter().max_by_key(|x| x.dem).unwrap();
+        let lowest = cities.iter().min_by_key(|x| x.dem).unwrap();
+        writeln!(out, "Mean population:  {}", total_population / count as i64)?;
+        writeln!(out, "Largest:          {} ({})", largest.name, largest.population)?;
+        writeln!(out, "Smallest:         {} ({})", smallest.name, smallest.population)?;
+        writeln!(out, "Highest:          {} ({} m)", highest.name, highest.dem)?;
+        writeln!(out, "Lowest:           {} ({} m)", lowest.name, lowest.dem)?;
+    }
+    Ok(())
+}
+
+pub fn main() -> Result<(), Box<dyn Error>> {
+    let options = match parse_arguments(std::env::args().skip(1).collect()) {
+        Ok(x) => x,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let cities = CityDataset::open_cached(&options.data)?.into_city_data();
+
+    let stdout = std::io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let format = options.format.unwrap_or(OutputFormat::Table);
+    match options.command {
+        Command::Query(query) => write_cities(&mut out, &query.run(&cities), format)?,
+        Command::Largest(group_by) => write_cities(&mut out, &largest(&cities, group_by), format)?,
+        Command::Stats(query) => print_stats(&mut out, &query.run(&cities))?,
+        Command::Nearest { coordinate, count } => {
+            let nearest = SpatialIndex::new(&cities).nearest(coordinate, count);
+            let nearest = nearest.into_iter().map(|(city, _)| city).collect::<Vec<_>>();
+            write_cities(&mut out, &nearest, format)?
+        }
+        Command::Within { coordinate, radius_km } => {
+            let within = SpatialIndex::new(&cities).within_radius(coordinate, radius_km);
+            let within = within.into_iter().map(|(city, _)| city).collect::<Vec<_>>();
+            write_cities(&mut out, &within, format)?
+        }
+        Command::Export { output, query } => {
+            let result = query.run(&cities);
+            let format = options.format.unwrap_or(OutputFormat::Json);
+            match output {
+                Some(path) => {
+                    let mut file = BufWriter::new(File::create(&path)?);
+                    write_cities(&mut file, &result, format)?;
+                    file.flush()?;
+                    eprintln!("Wrote {} cities to {}", result.len(), path.display());
+                }
+                None => write_cities(&mut out, &result, format)?,
+            }
+        }
+        Command::Map { output, query, title } => {
+            let result = query.run(&cities);
+            let red = [0xFF, 0x00, 0x00, 0xFF];
+            MapReport::new(1500, 750)
+                .title(&title)
+                .markers(result.iter().map(|city| Marker::city_by_population(city, red)))
+                .legend(&format!("{} cities", result.len()), red)
+                .colors([0xA0, 0xC8, 0xF0, 0xFF], [0xF0, 0xF0, 0xE0, 0xFF])
+                .save(&load_countries()?, &output)?;
+            eprintln!("Drew {} cities to {}", result.len(), output.display());
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
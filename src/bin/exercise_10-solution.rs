@@ -4,14 +4,54 @@
 /// all connected clients and let them guess the coordinates.
 /// When all clients have answered, send the answer to each of them and then
 /// print out the name of client that made the best guess to the console.
+///
+/// Every guess also scores points, and the totals are kept in leaderboard.json between runs.
+/// Rounds have a time limit, so a player who never guesses doesn't hold up everyone else.
+/// Players start in the main room, and can create and join other rooms with games of their own.
+/// Which cities come up depends on the difficulty, which can be narrowed down further, e.g. to
+/// the capitals of one continent.
+/// Every room picks its cities from the same seed, so a game can be played again city for city,
+/// and with --daily every server plays the same cities on the same day. A daily room starts over
+/// from the first city whenever someone joins it empty, and moves on to the next day's cities at
+/// midnight UTC.
+///
+/// > cargo run --bin exercise_10-solution -- --round-seconds 20
+/// > cargo run --bin exercise_10-solution -- --difficulty easy --region continent:Europe
+/// > cargo run --bin exercise_10-solution -- --daily
 
 use std::collections::HashMap;
 use std::io::Write;
 use std::net::TcpStream;
-use apricity::Coordinate;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant, SystemTime};
 use rand::prelude::*;
-use rustdemo::{City, load_cities};
+use rustdemo::{CityDataset, CitySelector, Leaderboard, Room, SeededRng, SelectionPolicy};
 use rustdemo::protocol::*;
+use rustdemo::room::{Outbox, DEFAULT_ROOM};
+use rustdemo::selection::{daily_seed, seeded_rng, Difficulty, Region, Weighting};
+
+const LEADERBOARD_PATH: &str = "leaderboard.json";
+const DEFAULT_ROUND_TIME: Duration = Duration::from_secs(30);
+
+const USAGE: &str = "\
+Usage: exercise_10-solution [--round-seconds <n>] [--seed <n> | --daily] [--difficulty <level>]
+                            [<selection options>]
+
+Options:
+  --round-seconds <n>       How long players have to guess each city (default: 30)
+  --seed <n>                Pick the cities from this seed, to play a game again (default: random)
+  --daily                   Play the daily challenge, the same cities as any other server with
+                            the same selection options plays that day
+  --difficulty <level>      easy, normal or hard (default: normal)
+
+Selection options, applied on top of the difficulty:
+  --min-population <n>      Only cities with at least this many people
+  --region <kind>:<name>    Only cities in a continent, country or timezone, e.g.
+                            continent:Europe, country:SE or timezone:Asia/Tokyo
+  --capitals                Only national capitals
+  --weighting <weighting>   uniform, or population to pick big cities more often
+  --repeats                 Allow a city to come up again before all have been played
+";
 
 // enable windows feature "telnet client"
 // then run
@@ -23,65 +63,286 @@ pub enum SocketEvent {
     Disconnect(u32),
 }
 
+struct Options {
+    round_time: Duration,
+    seed: Seed,
+    policy: SelectionPolicy,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Seed {
+    Fixed(u64),
+    /// The daily challenge, a new seed every day.
+    Daily,
+}
+
+impl Seed {
+    fn current(self) -> u64 {
+        match self {
+            Seed::Fixed(seed) => seed,
+            Seed::Daily => daily_seed(SystemTime::now()),
+        }
+    }
+}
+
+/// A room and the cities it picks from.
+struct Game<'a> {
+    room: Room,
+    selector: CitySelector<'a>,
+    seed: Seed,
+    /// What `rng` was seeded with, which for the daily challenge is the day it was started.
+    current_seed: u64,
+    rng: SeededRng,
+}
+
+impl<'a> Game<'a> {
+    /// Starts the first round, the first city of the seed.
+    fn new(room: Room, selector: CitySelector<'a>, seed: Seed) -> Game<'a> {
+        let current_seed = seed.current();
+        let mut game = Game { room, selector, seed, current_seed, rng: seeded_rng(current_seed) };
+        game.new_round(Instant::now());
+        game
+    }
+
+    /// Goes back to before the first city of the seed.
+    fn restart(&mut self) {
+        self.current_seed = self.seed.current();
+        self.rng = seeded_rng(self.current_seed);
+        self.selector.restart();
+    }
+
+    /// The daily challenge starts over with the new day's cities at midnight.
+    fn new_round(&mut self, now: Instant) -> Outbox {
+        if self.seed.current() != self.current_seed {
+            self.restart();
+        }
+        let city = self.selector.pick(&mut self.rng);
+        self.room.start_round(&city.name, city.coordinates, now)
+    }
+
+    /// Someone joining an empty daily room gets the day's cities from the first one.
+    fn join(&mut self, player: u32, name: &str, now: Instant) -> Outbox {
+        if matches!(self.seed, Seed::Daily) && self.room.is_empty() {
+            self.restart();
+            self.new_round(now);
+        }
+        self.room.join(player, name, now)
+    }
+}
+
+fn parse_arguments(arguments: Vec<String>) -> Result<Options, String> {
+    let mut round_time = DEFAULT_ROUND_TIME;
+    let mut seed = None;
+    let mut daily = false;
+    // The difficulty is the starting point whatever order the options come in
+    let mut difficulty = Difficulty::Normal;
+    let mut min_population = None;
+    let mut region = None;
+    let mut capitals_only = false;
+    let mut weighting = None;
+    let mut repeats = false;
+    let mut arguments = arguments.into_iter();
+    while let Some(argument) = arguments.next() {
+        let mut value = |name: &str| arguments.next().ok_or(format!("{} needs a value", name));
+        match argument.as_str() {
+            "--round-seconds" => {
+                let seconds = value("--round-seconds")?
+                    .parse::<u64>()
+                    .map_err(|_| "--round-seconds needs a whole number".to_string())?;
+                if seconds == 0 {
+                    return Err("--round-seconds can't be 0".to_string());
+                }
+                round_time = Duration::from_secs(seconds);
+            }
+            "--seed" => {
+                let value = value("--seed")?
+                    .parse::<u64>()
+                    .map_err(|_| "--seed needs a whole number".to_string())?;
+                seed = Some(value);
+            }
+            "--daily" => daily = true,
+            "--difficulty" => difficulty = value("--difficulty")?.parse()?,
+            "--min-population" => {
+                let population = value("--min-population")?
+                    .parse::<i64>()
+                    .map_err(|_| "--min-population needs a whole number".to_string())?;
+                min_population = Some(population);
+            }
+            "--region" => region = Some(value("--region")?.parse::<Region>()?),
+            "--capitals" => capitals_only = true,
+            "--weighting" => {
+                weighting = match value("--weighting")?.as_str() {
+                    "uniform" => Some(Weighting::Uniform),
+                    "population" => Some(Weighting::Population),
+                    other => return Err(format!("unknown weighting {:?}", other)),
+                }
+            }
+            "--repeats" => repeats = true,
+            other => return Err(format!("unknown argument {:?}", other)),
+        }
+    }
+    let seed = match (seed, daily) {
+        (Some(_), true) => return Err("--seed and --daily can't be used together".to_string()),
+        (Some(seed), false) => Seed::Fixed(seed),
+        (None, true) => Seed::Daily,
+        (None, false) => Seed::Fixed(thread_rng().gen()),
+    };
+    let mut policy = SelectionPolicy::for_difficulty(difficulty);
+    policy.min_population = min_population.unwrap_or(policy.min_population);
+    policy.region = region.or(policy.region);
+    policy.capitals_only |= capitals_only;
+    policy.weighting = weighting.unwrap_or(policy.weighting);
+    policy.no_repeats &= !repeats;
+    Ok(Options { round_time, seed, policy })
+}
+
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let Options { round_time, seed, policy } = match parse_arguments(std::env::args().skip(1).collect()) {
+        Ok(x) => x,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let cities = CityDataset::open_default()?.into_city_data();
+    if !cities.iter().any(|x| policy.allows(x)) {
+        eprintln!("No city fits the selection options");
+        std::process::exit(2);
+    }
+    // Only the main room's leaderboard is kept between runs. A file that can't be read is left
+    // alone instead of being overwritten with an empty leaderboard.
+    let leaderboard = Leaderboard::load(LEADERBOARD_PATH)
+        .map_err(|error| format!("Couldn't read {}: {}", LEADERBOARD_PATH, error))?;
+    match seed {
+        Seed::Fixed(seed) => println!("Playing seed {}", seed),
+        Seed::Daily => println!("Playing the daily challenge, today's seed is {}", seed.current()),
+    }
     let server_name = "Example implementation server".to_string();
     let (tx, rx) = std::sync::mpsc::channel::<SocketEvent>();
     std::thread::spawn(move || {
 
-        let cities = load_cities().unwrap();
         let mut sockets = HashMap::new();
         let mut names = HashMap::new();
-        let mut guesses = HashMap::<u32, Coordinate>::new();
-        let mut actual_location = Coordinate::new(0.0, 0.0);
-        let mut city_name = String::new();
-        let mut rng = thread_rng();
-        new_round(&cities, &mut sockets, &mut guesses, &mut actual_location, &mut city_name, &mut rng);
-        for event in rx {
+        // Which room each player is in. Players who left a room without joining another aren't in any.
+        let mut player_rooms = HashMap::<u32, String>::new();
+        let mut rooms = HashMap::<String, Game>::new();
+
+        let room = Room::new(DEFAULT_ROOM, round_time, leaderboard);
+        let selector = CitySelector::new(&cities, policy.clone()).unwrap();
+        rooms.insert(DEFAULT_ROOM.to_string(), Game::new(room, selector, seed));
+
+        // Wait for players, but no longer than until a round is over in some room
+        loop {
+            let next_deadline = rooms.values().map(|x| &x.room).filter(|x| !x.is_empty()).map(|x| x.deadline()).min();
+            let event = match next_deadline {
+                Some(deadline) => rx.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let mut outbox = Outbox::new();
             match event {
-                SocketEvent::Connect(socket_id, mut socket) => {
+                Ok(SocketEvent::Connect(socket_id, socket)) => {
                     sockets.insert(socket_id, socket);
                 }
-                SocketEvent::Message(socket_id, message) => {
+                Ok(SocketEvent::Message(socket_id, message)) => {
+                    let player_name = names.get(&socket_id).cloned().unwrap_or_default();
                     match message {
                         ClientMessage::Hello { name } => {
                             println!(r#""{}" says hello"#, name);
-                            if let Some(mut stream) = sockets.get(&socket_id) {
-                                names.entry(socket_id).or_insert(name);
-                                let welcome = bincode::serialize(&ServerMessage::Welcome { server_name: server_name.clone() }).unwrap();
-                                stream.write(&welcome).unwrap();
-                                let new_round = bincode::serialize(&ServerMessage::NewRound { city_name: city_name.clone() }).unwrap();
-                                stream.write(&new_round).unwrap();
+                            let taken = names.iter().any(|(id, x)| *id != socket_id && *x == name);
+                            if taken && !names.contains_key(&socket_id) {
+                                outbox.push((socket_id, ServerMessage::NameTaken { name }));
+                            } else if sockets.contains_key(&socket_id) {
+                                let name = names.entry(socket_id).or_insert(name).clone();
+                                let seed = rooms[DEFAULT_ROOM].current_seed;
+                                outbox.push((socket_id, ServerMessage::Welcome { server_name: server_name.clone(), seed }));
+                                if !player_rooms.contains_key(&socket_id) {
+                                    outbox.extend(join_room(&mut rooms, &mut player_rooms, socket_id, &name, DEFAULT_ROOM));
+                                }
+                            }
+                        }
+                        ClientMessage::Guess { round, coordinate } => {
+                            println!(r#"Got a guess from {} at {:?} for round {}"#, socket_id, coordinate, round);
+                            if let Some(game) = player_rooms.get(&socket_id).and_then(|x| rooms.get_mut(x)) {
+                                game.room.guess(socket_id, round, coordinate);
+                            }
+                        }
+                        // Rooms and leaderboards know players by name, so no room without one
+                        ClientMessage::CreateRoom { .. } | ClientMessage::JoinRoom { .. } if !names.contains_key(&socket_id) => {
+                            let message = "Say hello with a name before joining a room".to_string();
+                            outbox.push((socket_id, ServerMessage::RoomError { message }));
+                        }
+                        ClientMessage::ListRooms => {
+                            let mut list = rooms.values().map(|x| x.room.info()).collect::<Vec<_>>();
+                            list.sort_by(|a, b| a.name.cmp(&b.name));
+                            outbox.push((socket_id, ServerMessage::RoomList { rooms: list }));
+                        }
+                        ClientMessage::CreateRoom { name: room_name, policy: room_policy } => {
+                            // The name is checked first, the selector goes through every city
+                            if room_name.trim().is_empty() {
+                                let message = "A room needs a name".to_string();
+                                outbox.push((socket_id, ServerMessage::RoomError { message }));
+                            } else if rooms.contains_key(&room_name) {
+                                let message = format!("There already is a room called {:?}", room_name);
+                                outbox.push((socket_id, ServerMessage::RoomError { message }));
+                            } else if let Some(selector) = CitySelector::new(&cities, room_policy.unwrap_or_else(|| policy.clone())) {
+                                println!("{} created room {}", player_name, room_name);
+                                let room = Room::new(&room_name, round_time, Leaderboard::new());
+                                rooms.insert(room_name.clone(), Game::new(room, selector, seed));
+                                outbox.extend(join_room(&mut rooms, &mut player_rooms, socket_id, &player_name, &room_name));
+                            } else {
+                                let message = "No city fits the room's selection policy".to_string();
+                                outbox.push((socket_id, ServerMessage::RoomError { message }));
+                            }
+                        }
+                        ClientMessage::JoinRoom { name: room_name } if player_rooms.get(&socket_id) == Some(&room_name) => {
+                            let message = format!("You're already in room {:?}", room_name);
+                            outbox.push((socket_id, ServerMessage::RoomError { message }));
+                        }
+                        ClientMessage::JoinRoom { name: room_name } => {
+                            if rooms.contains_key(&room_name) {
+                                outbox.extend(join_room(&mut rooms, &mut player_rooms, socket_id, &player_name, &room_name));
+                            } else {
+                                let message = format!("There is no room called {:?}", room_name);
+                                outbox.push((socket_id, ServerMessage::RoomError { message }));
                             }
                         }
-                        ClientMessage::Guess(coordinate) => {
-                            println!(r#"Got a guess from {} at {:?}"#, socket_id, coordinate);
-                            guesses.entry(socket_id).or_insert(coordinate);
+                        ClientMessage::LeaveRoom => {
+                            leave_room(&mut rooms, &mut player_rooms, socket_id);
+                            outbox.push((socket_id, ServerMessage::LeftRoom));
                         }
                     }
                 }
-                SocketEvent::Disconnect(socket_id) => {
-                    guesses.remove(&socket_id);
+                Ok(SocketEvent::Disconnect(socket_id)) => {
+                    leave_room(&mut rooms, &mut player_rooms, socket_id);
                     sockets.remove(&socket_id);
                     names.remove(&socket_id);
                 }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
             }
-            if sockets.len() > 0 && guesses.len() == sockets.len() {
-                println!("End of round, had {} guesses and {} sockets", guesses.len(), sockets.len());
-                let best_guess = guesses.iter().min_by_key(|(current_id, current_coordinate)| {
-                    (actual_location.great_circle_distance(**current_coordinate) * 1000.0) as u64
-                });
-                if let Some((best_id, _))=  best_guess {
-                    if let Some(name) = names.get(best_id) {
-                        println!("{} was closest!", name);
-                    }
+
+            let now = Instant::now();
+            for game in rooms.values_mut().filter(|x| x.room.is_round_over(now)) {
+                let room = &mut game.room;
+                if room.all_guessed() {
+                    println!("End of round in {}, had {} guesses and {} players", room.name(), room.guess_count(), room.len());
+                } else {
+                    println!("Time is up in {}, had {} guesses and {} players", room.name(), room.guess_count(), room.len());
                 }
-                let round_results = bincode::serialize(&ServerMessage::RoundResults { actual_location }).unwrap();
-                for (_, mut stream) in sockets.iter() {
-                    stream.write(&round_results).unwrap();
+                let (rankings, messages) = room.end_round();
+                if let Some(best) = rankings.first() {
+                    println!("{} was closest!", best.name);
                 }
-                new_round(&cities, &mut sockets, &mut guesses, &mut actual_location, &mut city_name, &mut rng);
-                println!(r#"Next round, new city is {}"#, city_name);
+                outbox.extend(messages);
+                if room.name() == DEFAULT_ROOM {
+                    if let Err(error) = room.leaderboard().save(LEADERBOARD_PATH) {
+                        eprintln!("Couldn't save {}: {}", LEADERBOARD_PATH, error);
+                    }
+                }
+                outbox.extend(game.new_round(now));
+                println!(r#"Next round in {}, new city is {}"#, game.room.name(), game.room.city_name());
             }
+            send(&sockets, outbox);
         }
     });
     let listener = std::net::TcpListener::bind(("0.0.0.0", 12345))?;
@@ -113,14 +374,38 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn new_round(cities: &Vec<City>, sockets: &mut HashMap<u32, TcpStream>, guesses: &mut HashMap<u32, Coordinate>, actual_location: &mut Coordinate, city_name: &mut String, rng: &mut ThreadRng) {
-    let new_city = cities.choose(rng).unwrap();
-    *city_name = new_city.fields.name.to_string();
-    *actual_location = new_city.geometry.coordinates;
-    guesses.clear();
-    let new_round = bincode::serialize(&ServerMessage::NewRound { city_name: city_name.clone() }).unwrap();
-    for (socket_id, mut stream) in sockets.iter() {
-        println!("Sending new round to {}", socket_id);
-        stream.write(&new_round).unwrap();
+/// Moves a player from whatever room they're in to `room_name`, which has to exist.
+fn join_room(rooms: &mut HashMap<String, Game>, player_rooms: &mut HashMap<u32, String>, player: u32, name: &str, room_name: &str) -> Outbox {
+    if player_rooms.get(&player).map(|x| x.as_str()) != Some(room_name) {
+        leave_room(rooms, player_rooms, player);
+    }
+    let Some(game) = rooms.get_mut(room_name) else {
+        return Outbox::new();
+    };
+    player_rooms.insert(player, room_name.to_string());
+    game.join(player, name, Instant::now())
+}
+
+/// Takes a player out of their room, and closes the room if it's empty and not the main one.
+fn leave_room(rooms: &mut HashMap<String, Game>, player_rooms: &mut HashMap<u32, String>, player: u32) {
+    let Some(room_name) = player_rooms.remove(&player) else {
+        return;
+    };
+    if let Some(game) = rooms.get_mut(&room_name) {
+        game.room.leave(player);
+        if game.room.is_empty() && room_name != DEFAULT_ROOM {
+            println!("Closing room {}", room_name);
+            rooms.remove(&room_name);
+        }
+    }
+}
+
+fn send(sockets: &HashMap<u32, TcpStream>, outbox: Outbox) {
+    for (player, message) in outbox {
+        if let Some(mut stream) = sockets.get(&player) {
+            if let Err(error) = stream.write_all(&bincode::serialize(&message).unwrap()) {
+                eprintln!("Couldn't send to {}: {}", player, error);
+            }
+        }
     }
-}
\ No newline at end of file
+}
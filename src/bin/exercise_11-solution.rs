@@ -11,16 +11,28 @@
 /// along with circles for the clicked coordinate and the correct coordinate.
 ///
 /// When you're done, connect to the teacher server and play with others who are done.
+///
+/// > cargo run --bin exercise_11-solution -- --create-room friends --difficulty easy
+/// > cargo run --bin exercise_11-solution -- --join-room friends --projection robinson
 
 use std::error::Error;
 use std::io::Write;
 use std::net::TcpStream;
 use std::sync::mpsc::channel;
 use std::thread;
-use apricity::gui::{SimpleImage, Font, Event, Rect, MouseButton};
-use apricity::{Coordinate, Point};
-use rustdemo::helpers::exercise_11::draw_geo::create_world_map;
+use std::time::Instant;
+use apricity::gui::{SimpleImage, Font, Rect};
+use apricity::Coordinate;
+use rustdemo::geo::projection::ProjectionKind;
+use rustdemo::geo::viewport::{Viewport, ViewportInput};
+use rustdemo::helpers::exercise_11::draw_geo::{GAME_LAND_COLOR, GAME_SEA_COLOR};
 use rustdemo::protocol::{ClientMessage, ServerMessage};
+use rustdemo::selection::{Difficulty, SelectionPolicy};
+use rustdemo::ReverseGeocoder;
+
+const PLAYER_NAME: &str = "Gabriel";
+/// How many players of the leaderboard to show after each round.
+const LEADERBOARD_LINES: usize = 5;
 
 fn load_font() -> Font<'static> {
     Font::try_from_bytes(ttf_noto_sans::REGULAR).unwrap()
@@ -29,28 +41,75 @@ fn load_font() -> Font<'static> {
 enum GameState {
     Starting,
     Guessing {
+        /// Sent with the guess, so the server can tell it's not for the next city.
+        round: u32,
         city_name: String,
     },
+    // Coordinates rather than screen positions, since the map can be panned and zoomed
     Waiting {
-        guess: Point,
+        round: u32,
+        guess: Coordinate,
     },
     Reviewing {
-        guess: Point,
-        actual: Point,
+        round: u32,
+        /// `None` if time ran out before we guessed.
+        guess: Option<Coordinate>,
+        actual: Coordinate,
     },
 }
 
+impl GameState {
+    /// The round we're playing or reviewing, to tell which results are ours.
+    fn round(&self) -> Option<u32> {
+        match self {
+            GameState::Starting => None,
+            GameState::Guessing { round, .. } | GameState::Waiting { round, .. } | GameState::Reviewing { round, .. } => {
+                Some(*round)
+            }
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 struct TransitionInformation {
-    next_city: Option<String>,
-    next_actual_location: Option<Coordinate>,
+    /// The round number and the city name.
+    next_city: Option<(u32, String)>,
+    /// The round number and where its city was.
+    next_actual_location: Option<(u32, Coordinate)>,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let usage = "Usage: exercise_11-solution [--create-room <name> [--difficulty easy|normal|hard] | --join-room <name>] \
+                 [--projection equirectangular|mercator|robinson|orthographic]";
+    let mut arguments = std::env::args().skip(1).collect::<Vec<_>>();
+    let projection = match arguments.iter().position(|x| x == "--projection") {
+        Some(index) if index + 1 < arguments.len() => {
+            let name = arguments.remove(index + 1);
+            arguments.remove(index);
+            name.parse::<ProjectionKind>()?
+        }
+        Some(_) => return Err(usage.into()),
+        None => ProjectionKind::default(),
+    };
+
+    // Everyone starts in the server's main room
+    let room_request = match arguments.as_slice() {
+        [] => None,
+        [flag, name] if flag == "--create-room" => Some(ClientMessage::CreateRoom { name: name.clone(), policy: None }),
+        [flag, name, difficulty_flag, difficulty] if flag == "--create-room" && difficulty_flag == "--difficulty" => {
+            let policy = SelectionPolicy::for_difficulty(difficulty.parse::<Difficulty>()?);
+            Some(ClientMessage::CreateRoom { name: name.clone(), policy: Some(policy) })
+        }
+        [flag, name] if flag == "--join-room" => Some(ClientMessage::JoinRoom { name: name.clone() }),
+        _ => return Err(usage.into()),
+    };
+
     // Create background
     let width = 1500;
     let height = 750;
-    let background_image = create_world_map(width, height)?;
+    let geocoder = ReverseGeocoder::open_default()?;
+    let mut viewport = Viewport::with_projection(projection.projection(), width, height);
+    let mut background_image = viewport.render(geocoder.countries(), GAME_SEA_COLOR, GAME_LAND_COLOR);
 
     // Set up communication with the server
     let mut socket = TcpStream::connect(("127.0.0.1", 12345)).unwrap();
@@ -58,9 +117,12 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut socket_receive = socket.try_clone().unwrap();
     thread::spawn(move ||{
         let message = ClientMessage::Hello {
-            name: "Gabriel".to_string()
+            name: PLAYER_NAME.to_string()
         };
         socket_receive.write(&bincode::serialize(&message).unwrap()).unwrap();
+        if let Some(message) = room_request {
+            socket_receive.write_all(&bincode::serialize(&message).unwrap()).unwrap();
+        }
         while let Ok(message) = bincode::deserialize_from::<&TcpStream, ServerMessage>(&socket_receive) {
             tx.send(message).unwrap();
         }
@@ -76,6 +138,12 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut state = GameState::Starting;
     let mut transition_info = TransitionInformation::default();
     let mut current_text_image = SimpleImage::create_text_image(&font, "Please wait...", 72.0, [0xFF, 0x22, 0])?;
+    // Our points for the last round, and the best players overall
+    let mut score_image: Option<SimpleImage> = None;
+    let mut leaderboard_images: Vec<SimpleImage> = Vec::new();
+    // When the server ends the round, and the countdown last drawn with the seconds it shows
+    let mut round_deadline: Option<Instant> = None;
+    let mut timer_image: Option<(u64, SimpleImage)> = None;
 
     window.run((), |window, _, events| {
         // Render background
@@ -84,43 +152,111 @@ fn main() -> Result<(), Box<dyn Error>> {
         // Render guess and actual, if in the correct state
         let red = [0xFF, 0, 0, 0xFF];
         let blue = [0, 0, 0xFF, 0xFF];
-        match state {
-            GameState::Waiting { guess } => {
-                window.stroke_circle(guess.x, guess.y, 10.0, 1.0, red)?;
-            }
-            GameState::Reviewing { guess, actual } => {
-                window.stroke_circle(guess.x, guess.y, 10.0, 1.0, red)?;
-                window.stroke_circle(actual.x, actual.y, 10.0, 1.0, blue)?;
+        let markers = match state {
+            GameState::Waiting { guess, .. } => vec![(guess, red)],
+            GameState::Reviewing { guess: Some(guess), actual, .. } => vec![(guess, red), (actual, blue)],
+            GameState::Reviewing { guess: None, actual, .. } => vec![(actual, blue)],
+            _ => vec![],
+        };
+        // Markers the projection can't show, e.g. on the far side of a globe, are skipped
+        for (coordinate, color) in markers {
+            if let Some(point) = viewport.to_screen(coordinate) {
+                window.stroke_circle(point.x, point.y, 10.0, 1.0, color)?;
             }
-            _ => {}
         }
         // Render text
         let rect = Rect::new(10, 10, current_text_image.width(), current_text_image.height());
         window.draw_image(&current_text_image, Some(rect), true)?;
-        // Listen for clicks
+        if let (GameState::Guessing { .. } | GameState::Waiting { .. }, Some(deadline)) = (&state, round_deadline) {
+            let seconds = deadline.saturating_duration_since(Instant::now()).as_secs_f64().ceil() as u64;
+            if timer_image.as_ref().map(|(shown, _)| *shown) != Some(seconds) {
+                let image = SimpleImage::create_text_image(&font, &format!("{} s", seconds), 72.0, [0xFF, 0xFF, 0xFF])?;
+                timer_image = Some((seconds, image));
+            }
+            if let Some((_, image)) = &timer_image {
+                let rect = Rect::new(width as i32 - image.width() as i32 - 10, 10, image.width(), image.height());
+                window.draw_image(image, Some(rect), true)?;
+            }
+        }
+        if let GameState::Reviewing { .. } = state {
+            let mut y = 20 + current_text_image.height() as i32;
+            for image in score_image.iter().chain(&leaderboard_images) {
+                let rect = Rect::new(10, y, image.width(), image.height());
+                window.draw_image(image, Some(rect), true)?;
+                y += image.height() as i32 + 5;
+            }
+        }
+        // Listen for clicks, and pan and zoom the map
         let mut click_location = None;
+        let mut moved = false;
         for event in events {
-            match event {
-                Event::MouseButtonDown { mouse_btn, x, y, .. } => {
-                    if let MouseButton::Left = mouse_btn {
-                        click_location = Some(Point::new(x as f64, y as f64));
+            match viewport.handle_event(&event) {
+                // Clicks outside the map have no coordinate and are ignored
+                ViewportInput::Click(point) => {
+                    if let Some(coordinate) = viewport.to_coordinate(point) {
+                        click_location = Some(coordinate);
                     }
                 }
-                _ => {}
+                ViewportInput::Moved => moved = true,
+                ViewportInput::Ignored => {}
             }
         }
+        if moved {
+            background_image = viewport.render(geocoder.countries(), GAME_SEA_COLOR, GAME_LAND_COLOR);
+        }
         // Listen for messages
         match rx.try_recv().ok() {
-            Some(ServerMessage::Welcome { server_name }) => {
-                println!("Server {} welcomes you", server_name);
+            Some(ServerMessage::Welcome { server_name, seed }) => {
+                println!("Server {} welcomes you, playing seed {}", server_name, seed);
             }
-            Some(ServerMessage::NewRound { city_name }) => {
+            Some(ServerMessage::NewRound { round, city_name }) => {
                 println!("Next ciy: {}", city_name);
-                transition_info.next_city = Some(city_name);
+                transition_info.next_city = Some((round, city_name));
             }
-            Some(ServerMessage::RoundResults { actual_location }) => {
+            // Kept even before we're guessing that round, it may have ended before we got to it
+            Some(ServerMessage::RoundResults { round, actual_location }) => {
                 println!("Actual coordinate: {:?}", actual_location);
-                transition_info.next_actual_location = Some(actual_location);
+                transition_info.next_actual_location = Some((round, actual_location));
+            }
+            Some(ServerMessage::JoinedRoom { name }) => {
+                println!("Joined room {}", name);
+                // Whatever we were guessing was in the old room
+                state = GameState::Starting;
+                transition_info = TransitionInformation::default();
+                current_text_image = SimpleImage::create_text_image(&font, "Please wait...", 72.0, [0xFF, 0x22, 0])?;
+            }
+            Some(ServerMessage::RoomError { message }) => {
+                println!("{}", message);
+            }
+            Some(ServerMessage::NameTaken { name }) => {
+                return Err(format!("Someone called {} is already playing, change PLAYER_NAME", name).into());
+            }
+            Some(ServerMessage::RoundTimer { remaining }) => {
+                round_deadline = Some(Instant::now() + remaining);
+            }
+            // Rankings of a round we didn't play would show someone else's points
+            Some(ServerMessage::RoundRankings { round, rankings }) if state.round() == Some(round) => {
+                for (i, score) in rankings.iter().enumerate() {
+                    println!("{}. {}: {:.0} km, {} points", i + 1, score.name, score.distance_km, score.points);
+                }
+                score_image = match rankings.iter().position(|x| x.name == PLAYER_NAME) {
+                    Some(i) => {
+                        let text = format!("{} points, {} of {}", rankings[i].points, i + 1, rankings.len());
+                        Some(SimpleImage::create_text_image(&font, &text, 48.0, [0xFF, 0x22, 0])?)
+                    }
+                    None => None,
+                };
+            }
+            Some(ServerMessage::Leaderboard { standings }) => {
+                leaderboard_images = standings
+                    .iter()
+                    .take(LEADERBOARD_LINES)
+                    .enumerate()
+                    .map(|(i, x)| {
+                        let text = format!("{}. {}: {} points", i + 1, x.name, x.points);
+                        SimpleImage::create_text_image(&font, &text, 32.0, [0xFF, 0xFF, 0xFF])
+                    })
+                    .collect::<Result<_, _>>()?;
             }
             _ => {}
         }
@@ -136,37 +272,54 @@ fn main() -> Result<(), Box<dyn Error>> {
                 next_city: city_option @ Some(_),
                 ..
             }) => {
-                let city_name = city_option.take().unwrap();
+                let (round, city_name) = city_option.take().unwrap();
                 current_text_image = SimpleImage::create_text_image(&font, &format!("Where do you think {} is?", city_name), 72.0, [0xFF, 0x22, 0])?;
                 println!("Entering Guessing");
-                state = GameState::Guessing { city_name };
-                transition_info = TransitionInformation::default();
+                score_image = None;
+                state = GameState::Guessing { round, city_name };
+                // Results that already came for this round are kept, older ones are dropped
+                if transition_info.next_actual_location.is_some_and(|(x, _)| x != round) {
+                    transition_info.next_actual_location = None;
+                }
+            }
+
+            // If time ran out before we guessed, show where the city was
+            (GameState::Guessing { round, city_name }, _, TransitionInformation {
+                next_actual_location: Some((actual_round, actual_coordinate)),
+                ..
+            }) if actual_round == round => {
+                let (round, actual_coordinate) = (*round, *actual_coordinate);
+                current_text_image = SimpleImage::create_text_image(&font, &format!("Time's up! This is {}", city_name), 72.0, [0xFF, 0x22, 0])?;
+
+                println!("Entering Reviewing");
+                state = GameState::Reviewing { round, guess: None, actual: actual_coordinate };
+                transition_info.next_actual_location = None;
             }
 
             // When we've clicked somewhere to guess, start waiting
-            (GameState::Guessing { city_name }, Some(click), TransitionInformation { .. }) => {
-                let coordinate = click.coordinate(width as f64, height as f64);
-                let message = ClientMessage::Guess(coordinate);
+            (GameState::Guessing { round, .. }, Some(coordinate), TransitionInformation { .. }) => {
+                let round = *round;
+                let message = ClientMessage::Guess { round, coordinate };
                 socket.write(&bincode::serialize(&message)?).unwrap();
                 current_text_image = SimpleImage::create_text_image(&font, "Waiting for other players...", 72.0, [0xFF, 0x22, 0])?;
 
                 println!("Entering Waiting");
-                state = GameState::Waiting { guess: click };
+                state = GameState::Waiting { round, guess: coordinate };
             }
 
-            // When the last player is done, we review how we did
-            (GameState::Waiting { guess: guess_point }, _, TransitionInformation {
-                next_actual_location: actual_option @ Some(_),
+            // When the last player is done, we review how we did. Only the results of the round
+            // we guessed in count, even if the server got the guess too late to score it.
+            (GameState::Waiting { round, guess }, _, TransitionInformation {
+                next_actual_location: Some((actual_round, actual_coordinate)),
                 ..
-            }) => {
-                let actual_coordinate = actual_option.take().unwrap();
-                let actual_point = actual_coordinate.screen(width as f64, height as f64);
-                let guess_coordinate = guess_point.coordinate(width as f64, height as f64);
-                let distance = actual_coordinate.great_circle_distance(guess_coordinate);
-                current_text_image = SimpleImage::create_text_image(&font, &format!("You were {} km away", distance as u64), 72.0, [0xFF, 0x22, 0])?;
+            }) if actual_round == round => {
+                let (round, guess, actual_coordinate) = (*round, *guess, *actual_coordinate);
+                let distance = actual_coordinate.great_circle_distance(guess);
+                let guess_country = geocoder.country_at(guess).map_or("the sea", |x| x.name.as_str());
+                current_text_image = SimpleImage::create_text_image(&font, &format!("You were {} km away, in {}", distance as u64, guess_country), 72.0, [0xFF, 0x22, 0])?;
 
                 println!("Entering Reviewing");
-                state = GameState::Reviewing { guess: guess_point.clone(), actual: actual_point };
+                state = GameState::Reviewing { round, guess: Some(guess), actual: actual_coordinate };
                 transition_info.next_actual_location = None;
             }
             // If none of these conditions are fulfilled, don't change state at all.
@@ -15,45 +15,31 @@
 ///         }
 ///     }
 
-use std::collections::HashMap;
-use rustdemo::CityData;
+use rustdemo::geo::report::{MapReport, Marker};
+use rustdemo::geo::load_countries;
+use rustdemo::group::Groups;
+use rustdemo::CityDataset;
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cities = rustdemo::load_city_data()?;
+    let cities = CityDataset::open_default()?.into_city_data();
 
-    let cities = largest_city_for_each_country(cities);
-    let mut cities: Vec<CityData> = cities.iter().map(|(_, city)| city.clone()).collect();
-
-    cities.sort_by(|a,b| {
-        let country_name_a = a.country_name_eng();
-        let country_name_b = b.country_name_eng();
-        if country_name_a != country_name_b {
-            country_name_a.cmp(&country_name_b)
-        }
-        else {
-            a.name.cmp(&b.name)
-        }
-    });
+    // Groups are ordered by key, so this prints the countries alphabetically
+    let countries = Groups::new(&cities, |city| city.country_name_eng().to_string());
+    for (country_name, city) in countries.largest() {
+        println!("{:<40}: {:<25}", country_name, city.name);
+    }
 
-    for city in cities {
-        println!("{:<40}: {:<25}", city.country_name_eng(), city.name);
+    // Given a file name, e.g. `cargo run --bin exercise_4-solution -- largest.png`,
+    // also draw the cities on a world map, as SVG if the name ends in .svg
+    if let Some(path) = std::env::args().nth(1) {
+        let blue = [0x00, 0x00, 0xFF, 0xFF];
+        MapReport::new(3000, 1500)
+            .title("Largest city per country")
+            .markers(countries.largest().into_iter().map(|(_, city)| Marker::city(city, blue)))
+            .legend("Largest city", blue)
+            .save(&load_countries()?, &path)?;
+        println!("Wrote {}", path);
     }
 
     Ok(())
 }
-
-fn largest_city_for_each_country(city_data: Vec<CityData>) -> HashMap<String, CityData> {
-    let mut largest_cities = HashMap::<String, CityData>::new();
-    for city in city_data {
-        let country_name = city.country_name_eng();
-        largest_cities
-            .entry(country_name.to_string())
-            .and_modify(|current_city| {
-                if city.population > current_city.population {
-                    *current_city = city.clone();
-                }
-            })
-            .or_insert(city.clone());
-    };
-    largest_cities
-}
\ No newline at end of file
@@ -19,16 +19,30 @@
 ///     draw_geo::draw_image(window, &image, position_on_screen, Alignment::Left);
 
 use apricity::gui::*;
-use rustdemo::helpers::exercise_5::{city_parser::*, draw_geo::*, };
+use rustdemo::geo::map::{LAND_COLOR, SEA_COLOR};
+use rustdemo::geo::projection::ProjectionKind;
+use rustdemo::geo::{load_countries, Viewport, ViewportInput};
+use rustdemo::group::{self, Groups};
+use rustdemo::helpers::exercise_5::draw_geo::*;
+use rustdemo::{CityData, CityDataset};
 
 const WINDOW_WIDTH: u32 = 1500;
 const WINDOW_HEIGHT: u32 = 750;
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cities = load_city_data()?;
-    let world_map = create_world_map(WINDOW_WIDTH, WINDOW_HEIGHT)?;
+    let projection = match std::env::args().skip(1).collect::<Vec<_>>().as_slice() {
+        [] => ProjectionKind::default(),
+        [flag, name] if flag == "--projection" => name.parse::<ProjectionKind>()?,
+        _ => return Err("Usage: exercise_5-solution [--projection equirectangular|mercator|robinson|orthographic]".into()),
+    };
 
-    let largest_cities = get_largest_city_for_each_country(cities);
+    let cities = CityDataset::open_default()?.into_city_data();
+    let country_shapes = load_countries()?;
+    let mut viewport = Viewport::with_projection(projection.projection(), WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut world_map = viewport.render(&country_shapes, SEA_COLOR, LAND_COLOR);
+
+    let countries = Groups::new(&cities, group::country);
+    let largest_cities: Vec<&CityData> = countries.largest().into_iter().map(|(_, city)| city).collect();
 
     let window = SimpleWindow::new(WINDOW_WIDTH, WINDOW_HEIGHT)?;
 
@@ -42,7 +56,9 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         for city in &largest_cities {
 
-            let point = city.coordinates.screen(WINDOW_WIDTH as f64, WINDOW_HEIGHT as f64);
+            let Some(point) = viewport.to_screen(city.coordinates) else {
+                continue;
+            };
 
             window.stroke_circle(
                 point.x,
@@ -53,31 +69,23 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
             )?;
         }
 
+        // Scroll to zoom, drag to pan
+        let mut moved = false;
         for event in events {
-            if let Event::MouseButtonUp { mouse_btn, clicks, x, y, .. } = event {
-                let mouse_clicked = mouse_btn == MouseButton::Left && clicks == 1;
-                if mouse_clicked {
-                    println!("Mouse clicked at ({}, {})", x, y);
+            match viewport.handle_event(&event) {
+                ViewportInput::Click(point) => {
+                    println!("Mouse clicked at ({}, {})", point.x, point.y);
                 }
+                ViewportInput::Moved => moved = true,
+                ViewportInput::Ignored => {}
             }
         }
+        if moved {
+            world_map = viewport.render(&country_shapes, SEA_COLOR, LAND_COLOR);
+        }
 
         Ok(())
     })?;
 
     Ok(())
 }
-
-fn get_largest_city_for_each_country(city_data : Vec<CityData>) -> Vec<CityData> {
-    let mut result = city_data.clone();
-    result.sort_by(|x, y| {
-        if x.country_code == y.country_code {
-            y.population.cmp(&x.population)
-        }
-        else {
-            x.country_code.cmp(&y.country_code)
-        }
-    });
-    result.dedup_by_key(|x| x.country_code.clone());
-    result
-}
\ No newline at end of file
@@ -0,0 +1,72 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::City;
+
+/// Bump this whenever `City` or `CityData` changes shape, so old caches are thrown away.
+pub const CACHE_VERSION: u32 = 4;
+
+const MAGIC: [u8; 8] = *b"CITYBIN\0";
+
+/// Where the cache for the file at `source` is kept: next to it, with `.cache` added to the
+/// whole file name, so `cities15000.json` and `cities15000.txt` get a cache each.
+pub fn cache_path(source: &Path) -> PathBuf {
+    let mut path = source.as_os_str().to_owned();
+    path.push(".cache");
+    PathBuf::from(path)
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct CacheHeader {
+    magic: [u8; 8],
+    version: u32,
+    source_len: u64,
+    source_modified_secs: u64,
+    source_modified_nanos: u32,
+}
+
+impl CacheHeader {
+    fn for_source(source: &Path) -> std::io::Result<CacheHeader> {
+        let metadata = fs::metadata(source)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(CacheHeader {
+            magic: MAGIC,
+            version: CACHE_VERSION,
+            source_len: metadata.len(),
+            source_modified_secs: modified.as_secs(),
+            source_modified_nanos: modified.subsec_nanos(),
+        })
+    }
+}
+
+/// Reads the cached cities for `source`, if there is a cache and it still matches the source file.
+pub fn read(source: &Path) -> Option<Vec<City>> {
+    let expected = CacheHeader::for_source(source).ok()?;
+    let mut reader = BufReader::new(File::open(cache_path(source)).ok()?);
+    let header = bincode::deserialize_from::<_, CacheHeader>(&mut reader).ok()?;
+    if header != expected {
+        return None;
+    }
+    bincode::deserialize_from::<_, Vec<City>>(&mut reader).ok()
+}
+
+/// Writes `cities` as the cache for `source`.
+pub fn write(source: &Path, cities: &[City]) -> Result<(), Box<dyn std::error::Error>> {
+    let header = CacheHeader::for_source(source)?;
+    let cache_path = cache_path(source);
+    // Write to a temporary file first, so a reader never sees half a cache.
+    let temporary_path = cache_path.with_extension("cache.tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&temporary_path)?);
+        bincode::serialize_into(&mut writer, &header)?;
+        bincode::serialize_into(&mut writer, cities)?;
+        writer.flush()?;
+    }
+    fs::rename(&temporary_path, &cache_path)?;
+    Ok(())
+}
@@ -0,0 +1,569 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::geonames::{self, GeoNamesReader};
+use crate::{cache, City, CityData};
+
+/// File used when no other data source is given.
+pub const DEFAULT_CITIES_PATH: &str = "cities100k.json";
+
+/// Environment variable that overrides `DEFAULT_CITIES_PATH`.
+pub const CITIES_PATH_VARIABLE: &str = "CITIES_PATH";
+
+/// Path the binaries load from: `$CITIES_PATH` if set, otherwise `cities100k.json`.
+pub fn default_cities_path() -> PathBuf {
+    match std::env::var_os(CITIES_PATH_VARIABLE) {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(DEFAULT_CITIES_PATH),
+    }
+}
+
+/// An error loading city data, or the country borders, which are read the same way.
+#[derive(Debug)]
+pub enum DatasetError {
+    /// The data source couldn't be opened or read.
+    Io {
+        path: Option<PathBuf>,
+        source: std::io::Error,
+    },
+    /// The data isn't well-formed JSON, or not a JSON array of cities.
+    Syntax {
+        line: usize,
+        column: usize,
+        source: serde_json::Error,
+    },
+    /// A record is valid JSON but doesn't have the shape of a `City` or a GeoJSON feature.
+    Schema {
+        index: usize,
+        record_id: Option<String>,
+        source: serde_json::Error,
+    },
+    /// A line of a GeoNames dump has a missing or malformed column.
+    Column {
+        line: usize,
+        column: &'static str,
+        value: String,
+    },
+}
+
+impl DatasetError {
+    /// Wraps an error from parsing JSON that started at `line`/`column` of the source.
+    pub(crate) fn from_json(error: serde_json::Error, line: usize, column: usize) -> DatasetError {
+        match error.classify() {
+            serde_json::error::Category::Io => DatasetError::Io {
+                path: None,
+                source: error.into(),
+            },
+            _ if error.line() <= 1 => DatasetError::Syntax {
+                line,
+                column: column + error.column(),
+                source: error,
+            },
+            _ => DatasetError::Syntax {
+                line: line + error.line() - 1,
+                column: error.column(),
+                source: error,
+            },
+        }
+    }
+
+    fn unexpected(message: &str, line: usize, column: usize) -> DatasetError {
+        DatasetError::Syntax {
+            line,
+            column,
+            source: serde::de::Error::custom(message),
+        }
+    }
+
+    fn with_path(self, path: &Path) -> DatasetError {
+        match self {
+            DatasetError::Io { path: None, source } => DatasetError::Io {
+                path: Some(path.to_path_buf()),
+                source,
+            },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatasetError::Io { path: Some(path), source } => {
+                write!(f, "couldn't read {}: {}", path.display(), source)
+            }
+            DatasetError::Io { path: None, source } => {
+                write!(f, "couldn't read the data: {}", source)
+            }
+            DatasetError::Syntax { line, column, source } => {
+                write!(f, "malformed data at line {}, column {}: {}", line, column, source)
+            }
+            DatasetError::Schema { index, record_id: Some(record_id), source } => {
+                write!(f, "record {} (id {}) is invalid: {}", index, record_id, source)
+            }
+            DatasetError::Schema { index, record_id: None, source } => {
+                write!(f, "record {} is invalid: {}", index, source)
+            }
+            DatasetError::Column { line, column, value } => {
+                write!(f, "line {} has an invalid {} column: {:?}", line, column, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatasetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DatasetError::Io { source, .. } => Some(source),
+            DatasetError::Syntax { source, .. } => Some(source),
+            DatasetError::Schema { source, .. } => Some(source),
+            DatasetError::Column { .. } => None,
+        }
+    }
+}
+
+/// The cities from one OpenDataSoft JSON export or GeoNames dump.
+#[derive(Clone, Debug, Default)]
+pub struct CityDataset {
+    cities: Vec<City>,
+}
+
+impl CityDataset {
+    /// Loads the dataset from `default_cities_path()`, through the binary cache.
+    pub fn open_default() -> Result<CityDataset, DatasetError> {
+        CityDataset::open_cached(default_cities_path())
+    }
+
+    /// Like `open`, but reuses the binary cache next to `path` while it's up to date,
+    /// and writes a new one when it isn't.
+    pub fn open_cached(path: impl AsRef<Path>) -> Result<CityDataset, DatasetError> {
+        let path = path.as_ref();
+        if let Some(cities) = cache::read(path) {
+            return Ok(CityDataset { cities });
+        }
+        let dataset = CityDataset::open(path)?;
+        // The cache only saves time, so failing to write it (read-only directory etc.) isn't an error.
+        let _ = cache::write(path, &dataset.cities);
+        Ok(dataset)
+    }
+
+    /// Loads the dataset at `path`, which is read as a GeoNames dump if it ends in `.txt` or `.tsv`
+    /// and as JSON otherwise.
+    pub fn open(path: impl AsRef<Path>) -> Result<CityDataset, DatasetError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|source| DatasetError::Io {
+            path: Some(path.to_path_buf()),
+            source,
+        })?;
+        let dataset = if geonames::is_geonames_path(path) {
+            CityDataset::from_geonames_reader(BufReader::new(file))
+        } else {
+            CityDataset::from_reader(BufReader::new(file))
+        };
+        dataset.map_err(|error| error.with_path(path))
+    }
+
+    pub fn from_reader(reader: impl Read) -> Result<CityDataset, DatasetError> {
+        let cities = CityReader::new(BufReader::new(reader))
+            .records()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(CityDataset { cities })
+    }
+
+    pub fn from_geonames_reader(reader: impl BufRead) -> Result<CityDataset, DatasetError> {
+        let cities = GeoNamesReader::new(reader)
+            .records()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(CityDataset { cities })
+    }
+
+    pub fn cities(&self) -> &[City] {
+        &self.cities
+    }
+
+    pub fn len(&self) -> usize {
+        self.cities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cities.is_empty()
+    }
+
+    pub fn into_cities(self) -> Vec<City> {
+        self.cities
+    }
+
+    /// The `fields` of every city, with `coordinates` taken from `geometry`.
+    pub fn into_city_data(self) -> Vec<CityData> {
+        self.cities
+            .into_iter()
+            .map(City::into_city_data)
+            .collect()
+    }
+}
+
+/// Reads the cities of a JSON export one record at a time, without loading the whole file.
+pub struct CityReader<R> {
+    reader: R,
+    line: usize,
+    column: usize,
+}
+
+impl CityReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, DatasetError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|source| DatasetError::Io {
+            path: Some(path.to_path_buf()),
+            source,
+        })?;
+        Ok(CityReader::new(BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> CityReader<R> {
+    pub fn new(reader: R) -> Self {
+        CityReader {
+            reader,
+            line: 1,
+            column: 0,
+        }
+    }
+
+    /// Iterates over the records of the top-level array.
+    ///
+    /// A record that doesn't match the `City` schema yields a `DatasetError::Schema` and
+    /// iteration continues with the next one. Any other error ends the iteration.
+    pub fn records(self) -> Records<R> {
+        Records {
+            reader: self,
+            state: RecordsState::Start,
+            index: 0,
+        }
+    }
+
+    /// Like `records`, but yields the `fields` with `coordinates` taken from `geometry`.
+    pub fn city_data(self) -> impl Iterator<Item = Result<CityData, DatasetError>> {
+        self.records().map(|record| record.map(City::into_city_data))
+    }
+
+    /// The next byte that isn't whitespace, without consuming it.
+    fn peek_token(&mut self) -> Result<Option<u8>, DatasetError> {
+        loop {
+            let buffer = self.reader.fill_buf().map_err(|source| DatasetError::Io {
+                path: None,
+                source,
+            })?;
+            match buffer.first() {
+                None => return Ok(None),
+                Some(b' ' | b'\t' | b'\r') => self.consume(),
+                Some(b'\n') => {
+                    self.reader.consume(1);
+                    self.line += 1;
+                    self.column = 0;
+                }
+                Some(x) => return Ok(Some(*x)),
+            }
+        }
+    }
+
+    /// Consumes the byte returned by `peek_token`.
+    fn consume(&mut self) {
+        self.reader.consume(1);
+        self.column += 1;
+    }
+
+    fn advance(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            if *byte == b'\n' {
+                self.line += 1;
+                self.column = 0;
+            } else {
+                self.column += 1;
+            }
+        }
+    }
+
+    /// The next byte, whitespace or not, without consuming it.
+    fn peek_byte(&mut self) -> Result<Option<u8>, DatasetError> {
+        let buffer = self.reader.fill_buf().map_err(|source| DatasetError::Io {
+            path: None,
+            source,
+        })?;
+        Ok(buffer.first().copied())
+    }
+
+    fn read_record(&mut self) -> Result<serde_json::Value, DatasetError> {
+        let first = self.peek_token()?;
+        let (line, column) = (self.line, self.column);
+        // serde_json reads one byte past a number to find where it ends, which would swallow
+        // the `,` after it. Numbers are read up to the separator here instead.
+        if let Some(b'-' | b'0'..=b'9') = first {
+            let mut number = Vec::new();
+            while let Some(byte) = self.peek_byte()? {
+                if matches!(byte, b',' | b']' | b' ' | b'\t' | b'\r' | b'\n') {
+                    break;
+                }
+                number.push(byte);
+                self.consume();
+            }
+            return serde_json::from_slice(&number).map_err(|error| DatasetError::from_json(error, line, column));
+        }
+        let mut deserializer = serde_json::Deserializer::from_reader(PositionReader(self));
+        serde_json::Value::deserialize(&mut deserializer)
+            .map_err(|error| DatasetError::from_json(error, line, column))
+    }
+}
+
+// serde_json pulls one byte at a time, which lets us track the position it has reached.
+struct PositionReader<'a, R>(&'a mut CityReader<R>);
+
+impl<R: BufRead> Read for PositionReader<'_, R> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.0.reader.read(buffer)?;
+        self.0.advance(&buffer[..count]);
+        Ok(count)
+    }
+}
+
+enum RecordsState {
+    Start,
+    FirstRecord,
+    NextRecord,
+    Done,
+}
+
+/// Iterator returned by `CityReader::records`.
+pub struct Records<R> {
+    reader: CityReader<R>,
+    state: RecordsState,
+    index: usize,
+}
+
+impl<R: BufRead> Records<R> {
+    fn next_record(&mut self) -> Result<Option<City>, DatasetError> {
+        let reader = &mut self.reader;
+        if let RecordsState::Done = self.state {
+            return Ok(None);
+        }
+        if let RecordsState::Start = self.state {
+            match reader.peek_token()? {
+                Some(b'[') => reader.consume(),
+                _ => return Err(DatasetError::unexpected("expected `[`", reader.line, reader.column + 1)),
+            }
+            self.state = RecordsState::FirstRecord;
+        }
+
+        match (&self.state, reader.peek_token()?) {
+            (_, Some(b']')) => {
+                reader.consume();
+                self.state = RecordsState::Done;
+                if reader.peek_token()?.is_some() {
+                    return Err(DatasetError::unexpected("trailing characters", reader.line, reader.column + 1));
+                }
+                return Ok(None);
+            }
+            (RecordsState::NextRecord, Some(b',')) => reader.consume(),
+            (RecordsState::FirstRecord, Some(_)) => {}
+            (_, None) => return Err(DatasetError::unexpected("EOF while parsing a list", reader.line, reader.column)),
+            (_, Some(_)) => return Err(DatasetError::unexpected("expected `,` or `]`", reader.line, reader.column + 1)),
+        }
+        let index = self.index;
+        self.index += 1;
+        self.state = RecordsState::NextRecord;
+
+        let record = reader.read_record()?;
+        let record_id = record
+            .get("recordid")
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string());
+        serde_json::from_value::<City>(record)
+            .map(Some)
+            .map_err(|source| DatasetError::Schema {
+                index,
+                record_id,
+                source,
+            })
+    }
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+    type Item = Result<City, DatasetError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_record() {
+            Ok(record) => record.map(Ok),
+            Err(error @ DatasetError::Schema { .. }) => Some(Err(error)),
+            Err(error) => {
+                self.state = RecordsState::Done;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOTTINGEN: &str = r#"{
+        "datasetid": "geonames-all-cities-with-a-population-1000@public",
+        "recordid": "79935cd6ad4e4b4fb035208337e0ea8b9367f55e",
+        "fields": {
+            "coordinates": [51.53443, 9.93228],
+            "cou_name_en": "Germany",
+            "label_en": "Germany",
+            "feature_code": "PPLA3",
+            "population": 122149,
+            "dem": 153,
+            "geoname_id": "2918632",
+            "admin4_code": "03159016",
+            "name": "Göttingen",
+            "ascii_name": "Goettingen",
+            "alternate_names": "Goettingen,Gottingen,Гёттинген",
+            "admin1_code": "06",
+            "admin3_code": "03159",
+            "feature_class": "P",
+            "country_code": "DE",
+            "admin2_code": "00",
+            "timezone": "Europe/Berlin",
+            "modification_date": "2019-09-05"
+        },
+        "geometry": {"type": "Point", "coordinates": [9.93228, 51.53443]},
+        "record_timestamp": "2022-10-10T08:00:01.602+02:00"
+    }"#;
+
+    #[test]
+    fn test_load_from_reader() {
+        let json = format!("[{}]", GOTTINGEN);
+        let dataset = CityDataset::from_reader(json.as_bytes()).unwrap();
+        assert_eq!(dataset.len(), 1);
+        let city_data = dataset.into_city_data();
+        assert_eq!(city_data[0].name, "Göttingen");
+        assert_eq!(city_data[0].population, 122149);
+    }
+
+    #[test]
+    fn test_syntax_error_position() {
+        let json = "[\n  {\"recordid\": }\n]";
+        match CityDataset::from_reader(json.as_bytes()) {
+            Err(DatasetError::Syntax { line, column, .. }) => {
+                assert_eq!(line, 2);
+                assert_eq!(column, 16);
+            }
+            other => panic!("Expected a syntax error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_schema_error_record() {
+        let json = format!(r#"[{}, {{"recordid": "broken"}}]"#, GOTTINGEN);
+        match CityDataset::from_reader(json.as_bytes()) {
+            Err(DatasetError::Schema { index, record_id, .. }) => {
+                assert_eq!(index, 1);
+                assert_eq!(record_id.as_deref(), Some("broken"));
+            }
+            other => panic!("Expected a schema error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_stream_records() {
+        let json = format!("[\n{},\n{{\"recordid\": \"broken\"}},\n{}\n]", GOTTINGEN, GOTTINGEN);
+        let records = CityReader::new(json.as_bytes()).records().collect::<Vec<_>>();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].as_ref().unwrap().fields.name, "Göttingen");
+        assert!(matches!(records[1], Err(DatasetError::Schema { index: 1, .. })));
+        assert_eq!(records[2].as_ref().unwrap().recordid, "79935cd6ad4e4b4fb035208337e0ea8b9367f55e");
+    }
+
+    #[test]
+    fn test_stream_continues_after_scalar() {
+        let json = format!("[{}, 1, \"text\", -2.5e3,\n{}]", GOTTINGEN, GOTTINGEN);
+        let records = CityReader::new(json.as_bytes()).records().collect::<Vec<_>>();
+        assert_eq!(records.len(), 5);
+        assert!(matches!(records[1], Err(DatasetError::Schema { index: 1, .. })));
+        assert!(matches!(records[2], Err(DatasetError::Schema { index: 2, .. })));
+        assert!(matches!(records[3], Err(DatasetError::Schema { index: 3, .. })));
+        assert_eq!(records[4].as_ref().unwrap().fields.name, "Göttingen");
+    }
+
+    #[test]
+    fn test_stream_stops_on_syntax_error() {
+        let json = format!("[{},\n  {{\"recordid\" 1}}, {}]", GOTTINGEN, GOTTINGEN);
+        let mut records = CityReader::new(json.as_bytes()).records();
+        assert!(records.next().unwrap().is_ok());
+        match records.next() {
+            Some(Err(DatasetError::Syntax { line, column, .. })) => {
+                assert_eq!(line, 27);
+                assert_eq!(column, 15);
+            }
+            other => panic!("Expected a syntax error, got {:?}", other.map(|x| x.map(|_| ()))),
+        }
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_empty_array() {
+        assert_eq!(CityReader::new(" [ ] ".as_bytes()).records().count(), 0);
+    }
+
+    #[test]
+    fn test_trailing_content() {
+        let json = format!("[{}]\n[{}]", GOTTINGEN, GOTTINGEN);
+        let records = CityReader::new(json.as_bytes()).records().collect::<Vec<_>>();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].is_ok());
+        match &records[1] {
+            Err(DatasetError::Syntax { line, column, .. }) => {
+                assert_eq!(*line, 27);
+                assert_eq!(*column, 1);
+            }
+            other => panic!("Expected a syntax error, got {:?}", other.as_ref().map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let directory = std::env::temp_dir().join(format!("rustdemo-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let source = directory.join("cities.json");
+        std::fs::write(&source, format!("[{}]", GOTTINGEN)).unwrap();
+
+        assert!(cache::read(&source).is_none());
+        let dataset = CityDataset::open_cached(&source).unwrap();
+        let cached = cache::read(&source).unwrap();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].fields.name, dataset.cities()[0].fields.name);
+        assert_eq!(cached[0].fields.coordinates, dataset.cities()[0].fields.coordinates);
+        assert_eq!(cached[0].fields.alternate_names, ["Goettingen", "Gottingen", "Гёттинген"]);
+
+        // A changed source invalidates the cache
+        std::fs::write(&source, "[]").unwrap();
+        assert!(cache::read(&source).is_none());
+        assert!(CityDataset::open_cached(&source).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_cache_path_per_source() {
+        assert_eq!(cache::cache_path(Path::new("data/cities15000.json")), PathBuf::from("data/cities15000.json.cache"));
+        assert_ne!(cache::cache_path(Path::new("cities15000.json")), cache::cache_path(Path::new("cities15000.txt")));
+    }
+
+    #[test]
+    fn test_missing_file() {
+        match CityDataset::open("does/not/exist.json") {
+            Err(DatasetError::Io { path: Some(path), .. }) => {
+                assert_eq!(path, PathBuf::from("does/not/exist.json"));
+            }
+            other => panic!("Expected an I/O error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::path::Path;
+
+use apricity::Coordinate;
+
+use super::geojson::{self, Feature};
+use crate::spatial::EARTH_RADIUS_KM;
+use crate::{BoundingBox, DatasetError};
+
+pub const COUNTRIES_PATH: &str = "countries.geojson";
+
+/// One connected piece of a country: an outer ring, minus any holes such as lakes or enclaves.
+/// Rings are closed, the last coordinate repeats the first.
+#[derive(Clone, Debug)]
+pub struct Polygon {
+    pub exterior: Vec<Coordinate>,
+    pub holes: Vec<Vec<Coordinate>>,
+    pub bounds: BoundingBox,
+}
+
+impl Polygon {
+    /// The first ring is the outline, the rest are holes, as in a GeoJSON Polygon.
+    pub fn from_rings(rings: &[Vec<Coordinate>]) -> Polygon {
+        let exterior = rings.first().cloned().unwrap_or_default();
+        let holes = rings.iter().skip(1).cloned().collect();
+        let bounds = bounding_box(exterior.iter());
+        Polygon { exterior, holes, bounds }
+    }
+
+    pub fn contains(&self, coordinate: Coordinate) -> bool {
+        self.bounds.contains(coordinate)
+            && ring_contains(&self.exterior, coordinate)
+            && !self.holes.iter().any(|hole| ring_contains(hole, coordinate))
+    }
+
+    /// Whether `coordinate` is inside one of the holes.
+    pub fn hole_contains(&self, coordinate: Coordinate) -> bool {
+        self.bounds.contains(coordinate) && self.holes.iter().any(|hole| ring_contains(hole, coordinate))
+    }
+
+    /// A point strictly inside the polygon, which a vertex isn't: the middle of the first span
+    /// it covers along the latitude halfway between its northmost and southmost points.
+    pub fn interior_point(&self) -> Option<Coordinate> {
+        let latitude = (self.bounds.south + self.bounds.north) / 2.0;
+        let mut crossings = std::iter::once(&self.exterior)
+            .chain(&self.holes)
+            .flat_map(|ring| ring_crossings(ring, latitude))
+            .collect::<Vec<_>>();
+        crossings.sort_by(f64::total_cmp);
+        match crossings[..] {
+            [west, east, ..] => Some(Coordinate { latitude, longitude: (west + east) / 2.0 }),
+            _ => None,
+        }
+    }
+
+    /// Area on the sphere in km², not counting the holes.
+    pub fn area_km2(&self) -> f64 {
+        let holes = self.holes.iter().map(|x| ring_area_km2(x)).sum::<f64>();
+        (ring_area_km2(&self.exterior) - holes).max(0.0)
+    }
+}
+
+/// Even-odd ray casting along the latitude of `coordinate`.
+fn ring_contains(ring: &[Coordinate], coordinate: Coordinate) -> bool {
+    let crossings = ring_crossings(ring, coordinate.latitude);
+    crossings.filter(|&x| coordinate.longitude < x).count() % 2 == 1
+}
+
+/// The longitudes where the edges of `ring` cross `latitude`.
+fn ring_crossings(ring: &[Coordinate], latitude: f64) -> impl Iterator<Item = f64> + '_ {
+    let y = latitude;
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .filter(move |(a, b)| (a.latitude > y) != (b.latitude > y))
+        .map(move |(a, b)| a.longitude + (y - a.latitude) / (b.latitude - a.latitude) * (b.longitude - a.longitude))
+}
+
+/// The area enclosed by a ring on the sphere, whichever way it winds.
+/// See Chamberlain & Duquette, "Some Algorithms for Polygons on a Sphere".
+fn ring_area_km2(ring: &[Coordinate]) -> f64 {
+    let sum = ring
+        .iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|(a, b)| {
+            let longitude_difference = (b.longitude - a.longitude).to_radians();
+            longitude_difference * (2.0 + a.latitude.to_radians().sin() + b.latitude.to_radians().sin())
+        })
+        .sum::<f64>();
+    (sum * EARTH_RADIUS_KM * EARTH_RADIUS_KM / 2.0).abs()
+}
+
+fn bounding_box<'a>(coordinates: impl Iterator<Item = &'a Coordinate>) -> BoundingBox {
+    let mut bounds = BoundingBox::new(f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for coordinate in coordinates {
+        bounds.south = bounds.south.min(coordinate.latitude);
+        bounds.north = bounds.north.max(coordinate.latitude);
+        bounds.west = bounds.west.min(coordinate.longitude);
+        bounds.east = bounds.east.max(coordinate.longitude);
+    }
+    bounds
+}
+
+/// The borders of a country from `countries.geojson`.
+#[derive(Clone, Debug)]
+pub struct CountryShape {
+    /// The `ADMIN` name, e.g. "Sweden".
+    pub name: String,
+    pub iso_a2: Option<String>,
+    pub iso_a3: Option<String>,
+    pub polygons: Vec<Polygon>,
+    /// Covers all of `polygons`.
+    pub bounds: BoundingBox,
+}
+
+impl CountryShape {
+    pub fn contains(&self, coordinate: Coordinate) -> bool {
+        self.bounds.contains(coordinate) && self.polygons.iter().any(|x| x.contains(coordinate))
+    }
+
+    /// Area on the sphere in km², without lakes and enclaves that are holes in the borders.
+    pub fn area_km2(&self) -> f64 {
+        self.polygons.iter().map(|x| x.area_km2()).sum()
+    }
+}
+
+/// Natural Earth uses "-99" for countries without an ISO code.
+fn iso_code(code: Option<String>) -> Option<String> {
+    code.filter(|x| !x.is_empty() && x != "-99")
+}
+
+pub fn load_countries() -> Result<Vec<CountryShape>, DatasetError> {
+    read_countries(COUNTRIES_PATH)
+}
+
+pub fn read_countries(path: impl AsRef<Path>) -> Result<Vec<CountryShape>, DatasetError> {
+    countries_from_features(geojson::read_geojson(path)?.features)
+}
+
+/// Parses a GeoJSON FeatureCollection with the Natural Earth `ADMIN`, `ISO_A2` and `ISO_A3`
+/// properties. Features without any polygons are skipped.
+pub fn parse_countries(json: &str) -> Result<Vec<CountryShape>, DatasetError> {
+    countries_from_features(geojson::parse_geojson(json)?.features)
+}
+
+fn countries_from_features(features: Vec<Feature>) -> Result<Vec<CountryShape>, DatasetError> {
+    let mut countries = Vec::with_capacity(features.len());
+    for (index, feature) in features.into_iter().enumerate() {
+        let Some(name) = feature.property_str("ADMIN") else {
+            return Err(DatasetError::Schema {
+                index,
+                record_id: feature.id.map(|x| x.as_str().map(str::to_string).unwrap_or_else(|| x.to_string())),
+                source: serde::de::Error::custom("missing the ADMIN property"),
+            });
+        };
+        let polygons = match &feature.geometry {
+            Some(geometry) => geometry.polygons().into_iter().map(Polygon::from_rings).collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+        if polygons.is_empty() {
+            continue;
+        }
+        let iso_code = |name| iso_code(feature.property_str(name).map(|x| x.to_string()));
+        countries.push(CountryShape {
+            name: name.to_string(),
+            iso_a2: iso_code("ISO_A2"),
+            iso_a3: iso_code("ISO_A3"),
+            bounds: bounding_box(polygons.iter().flat_map(|x| x.exterior.iter())),
+            polygons,
+        });
+    }
+    Ok(countries)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A square country with a square hole, and a country made of two islands.
+    pub(crate) const COUNTRIES: &str = r#"{
+        "type": "FeatureCollection",
+        "features": [
+            {
+                "type": "Feature",
+                "properties": { "ADMIN": "Squareland", "ISO_A2": "SQ", "ISO_A3": "SQR" },
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [
+                        [[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]],
+                        [[4, 4], [6, 4], [6, 6], [4, 6], [4, 4]]
+                    ]
+                }
+            },
+            {
+                "type": "Feature",
+                "properties": { "ADMIN": "Islands", "ISO_A2": "-99", "ISO_A3": "ISL" },
+                "geometry": {
+                    "type": "MultiPolygon",
+                    "coordinates": [
+                        [[[20, -5], [22, -5], [21, -3], [20, -5]]],
+                        [[[30, 40], [32, 40], [32, 42], [30, 42], [30, 40]]]
+                    ]
+                }
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse_countries() {
+        let countries = parse_countries(COUNTRIES).unwrap();
+        assert_eq!(countries[0].iso_a2.as_deref(), Some("SQ"));
+        assert_eq!(countries[0].iso_a3.as_deref(), Some("SQR"));
+        assert_eq!(countries[0].polygons[0].holes.len(), 1);
+        assert_eq!(countries[1].iso_a2, None);
+        assert_eq!(countries[1].bounds, BoundingBox::new(-5.0, 20.0, 42.0, 32.0));
+    }
+
+    #[test]
+    fn test_interior_point() {
+        let countries = parse_countries(COUNTRIES).unwrap();
+        let triangle = &countries[1].polygons[0];
+        let point = triangle.interior_point().unwrap();
+        assert_eq!((point.latitude, point.longitude), (-4.0, 21.0));
+        assert!(triangle.contains(point));
+        // Halfway up the square is its hole, the point is west of it
+        let square = &countries[0].polygons[0];
+        let point = square.interior_point().unwrap();
+        assert_eq!((point.latitude, point.longitude), (5.0, 2.0));
+        assert!(square.contains(point));
+    }
+
+    #[test]
+    fn test_area() {
+        let countries = parse_countries(COUNTRIES).unwrap();
+        // 10 by 10 degrees at the equator is about 1.23 million km², the hole 4 by 4 degrees less
+        let square = countries[0].area_km2();
+        let hole = ring_area_km2(&countries[0].polygons[0].holes[0]);
+        assert!((square + hole - 1_233_000.0).abs() < 5_000.0, "{}", square + hole);
+        assert!((hole - 49_400.0).abs() < 500.0, "{}", hole);
+    }
+}
@@ -0,0 +1,246 @@
+use std::fs;
+use std::path::Path;
+
+use apricity::Coordinate;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::DatasetError;
+
+/// A GeoJSON geometry. Positions are read as `[longitude, latitude]`; an altitude, if any,
+/// is ignored.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(from = "RawGeometry")]
+pub enum Geometry {
+    Point(Coordinate),
+    MultiPoint(Vec<Coordinate>),
+    LineString(Vec<Coordinate>),
+    MultiLineString(Vec<Vec<Coordinate>>),
+    /// The first ring is the outline, the rest are holes.
+    Polygon(Vec<Vec<Coordinate>>),
+    MultiPolygon(Vec<Vec<Vec<Coordinate>>>),
+    GeometryCollection(Vec<Geometry>),
+}
+
+impl Geometry {
+    /// Every polygon in the geometry, including those inside a GeometryCollection.
+    pub fn polygons(&self) -> Vec<&[Vec<Coordinate>]> {
+        match self {
+            Geometry::Polygon(rings) => vec![rings.as_slice()],
+            Geometry::MultiPolygon(polygons) => polygons.iter().map(|x| x.as_slice()).collect(),
+            Geometry::GeometryCollection(geometries) => geometries.iter().flat_map(|x| x.polygons()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Feature {
+    #[serde(default)]
+    pub id: Option<Value>,
+    /// `None` for features with a `null` geometry.
+    pub geometry: Option<Geometry>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub properties: Map<String, Value>,
+}
+
+impl Feature {
+    pub fn property(&self, name: &str) -> Option<&Value> {
+        self.properties.get(name)
+    }
+
+    /// The property `name` if it's a string.
+    pub fn property_str(&self, name: &str) -> Option<&str> {
+        self.property(name).and_then(|x| x.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FeatureCollection {
+    pub features: Vec<Feature>,
+}
+
+fn null_as_empty<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Map<String, Value>, D::Error> {
+    Ok(Option::<Map<String, Value>>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "Vec<f64>")]
+struct Position(Coordinate);
+
+impl TryFrom<Vec<f64>> for Position {
+    type Error = String;
+
+    fn try_from(values: Vec<f64>) -> Result<Self, Self::Error> {
+        match values[..] {
+            [longitude, latitude, ..] => Ok(Position(Coordinate { latitude, longitude })),
+            _ => Err(format!("a position needs a longitude and a latitude, got {:?}", values)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum RawGeometry {
+    Point { coordinates: Position },
+    MultiPoint { coordinates: Vec<Position> },
+    LineString { coordinates: Vec<Position> },
+    MultiLineString { coordinates: Vec<Vec<Position>> },
+    Polygon { coordinates: Vec<Vec<Position>> },
+    MultiPolygon { coordinates: Vec<Vec<Vec<Position>>> },
+    GeometryCollection { geometries: Vec<Geometry> },
+}
+
+fn line(positions: Vec<Position>) -> Vec<Coordinate> {
+    positions.into_iter().map(|x| x.0).collect()
+}
+
+fn lines(lines: Vec<Vec<Position>>) -> Vec<Vec<Coordinate>> {
+    lines.into_iter().map(line).collect()
+}
+
+impl From<RawGeometry> for Geometry {
+    fn from(raw: RawGeometry) -> Self {
+        match raw {
+            RawGeometry::Point { coordinates } => Geometry::Point(coordinates.0),
+            RawGeometry::MultiPoint { coordinates } => Geometry::MultiPoint(line(coordinates)),
+            RawGeometry::LineString { coordinates } => Geometry::LineString(line(coordinates)),
+            RawGeometry::MultiLineString { coordinates } => Geometry::MultiLineString(lines(coordinates)),
+            RawGeometry::Polygon { coordinates } => Geometry::Polygon(lines(coordinates)),
+            RawGeometry::MultiPolygon { coordinates } => {
+                Geometry::MultiPolygon(coordinates.into_iter().map(lines).collect())
+            }
+            RawGeometry::GeometryCollection { geometries } => Geometry::GeometryCollection(geometries),
+        }
+    }
+}
+
+pub fn read_geojson(path: impl AsRef<Path>) -> Result<FeatureCollection, DatasetError> {
+    let path = path.as_ref();
+    let json = fs::read_to_string(path).map_err(|source| DatasetError::Io {
+        path: Some(path.to_path_buf()),
+        source,
+    })?;
+    parse_geojson(&json)
+}
+
+/// Parses a FeatureCollection, a single Feature or a bare geometry. The last two become a
+/// collection of one feature.
+///
+/// A feature that doesn't have the shape of a GeoJSON feature is a `DatasetError::Schema`
+/// with its index in the collection.
+pub fn parse_geojson(json: &str) -> Result<FeatureCollection, DatasetError> {
+    let mut document =
+        serde_json::from_str::<Value>(json).map_err(|error| DatasetError::from_json(error, 1, 0))?;
+    let features = match document.get("type").and_then(|x| x.as_str()) {
+        Some("FeatureCollection") => match document.get_mut("features").map(Value::take) {
+            Some(Value::Array(features)) => features,
+            _ => return Err(schema_error(0, None, "a FeatureCollection needs a features array")),
+        },
+        Some("Feature") => vec![document],
+        _ => {
+            let geometry = serde_json::from_value::<Geometry>(document)
+                .map_err(|source| DatasetError::Schema { index: 0, record_id: None, source })?;
+            return Ok(FeatureCollection {
+                features: vec![Feature {
+                    id: None,
+                    geometry: Some(geometry),
+                    properties: Map::new(),
+                }],
+            });
+        }
+    };
+
+    let features = features
+        .into_iter()
+        .enumerate()
+        .map(|(index, feature)| {
+            let record_id = feature.get("id").map(|x| match x {
+                Value::String(id) => id.clone(),
+                other => other.to_string(),
+            });
+            serde_json::from_value::<Feature>(feature).map_err(|source| DatasetError::Schema {
+                index,
+                record_id,
+                source,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(FeatureCollection { features })
+}
+
+fn schema_error(index: usize, record_id: Option<String>, message: &str) -> DatasetError {
+    DatasetError::Schema {
+        index,
+        record_id,
+        source: serde::de::Error::custom(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lat_lon(coordinate: &Coordinate) -> (f64, f64) {
+        (coordinate.latitude, coordinate.longitude)
+    }
+
+    #[test]
+    fn test_geometry_types() {
+        let collection = parse_geojson(r#"{
+            "type": "FeatureCollection",
+            "features": [
+                { "type": "Feature", "properties": { "name": "Stockholm" },
+                  "geometry": { "type": "Point", "coordinates": [18.07, 59.33, 28] } },
+                { "type": "Feature", "properties": null,
+                  "geometry": { "type": "LineString", "coordinates": [[0, 0], [1, 2]] } },
+                { "type": "Feature", "properties": {},
+                  "geometry": { "type": "GeometryCollection", "geometries": [
+                      { "type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]] },
+                      { "type": "MultiPolygon", "coordinates": [[[[5, 5], [6, 5], [6, 6], [5, 5]]]] },
+                      { "type": "MultiPoint", "coordinates": [[3, 4]] }
+                  ] } },
+                { "type": "Feature", "properties": {}, "geometry": null }
+            ]
+        }"#)
+        .unwrap();
+        let features = &collection.features;
+        assert_eq!(features[0].property_str("name"), Some("Stockholm"));
+        match &features[0].geometry {
+            Some(Geometry::Point(point)) => assert_eq!(lat_lon(point), (59.33, 18.07)),
+            other => panic!("expected a point, got {:?}", other),
+        }
+        match &features[1].geometry {
+            Some(Geometry::LineString(line)) => assert_eq!(line.iter().map(lat_lon).collect::<Vec<_>>(), [(0.0, 0.0), (2.0, 1.0)]),
+            other => panic!("expected a line, got {:?}", other),
+        }
+        assert!(features[1].properties.is_empty());
+        assert_eq!(features[2].geometry.as_ref().unwrap().polygons().len(), 2);
+        assert!(features[3].geometry.is_none());
+    }
+
+    #[test]
+    fn test_bare_geometry() {
+        let collection = parse_geojson(r#"{ "type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]] }"#).unwrap();
+        assert_eq!(collection.features.len(), 1);
+        assert_eq!(collection.features[0].geometry.as_ref().unwrap().polygons().len(), 1);
+    }
+
+    #[test]
+    fn test_schema_error_index() {
+        let result = parse_geojson(r#"{
+            "type": "FeatureCollection",
+            "features": [
+                { "type": "Feature", "properties": {}, "geometry": { "type": "Point", "coordinates": [1, 2] } },
+                { "type": "Feature", "id": "bad", "properties": {}, "geometry": { "type": "Point", "coordinates": [1] } }
+            ]
+        }"#);
+        match result {
+            Err(DatasetError::Schema { index, record_id, .. }) => {
+                assert_eq!(index, 1);
+                assert_eq!(record_id.as_deref(), Some("bad"));
+            }
+            other => panic!("expected a schema error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::error::Error;
+
+use apricity::gui::*;
+use apricity::{Coordinate, Point};
+
+use super::countries::{load_countries, CountryShape};
+use super::geojson::{FeatureCollection, Geometry};
+use super::projection::{Equirectangular, Projection};
+use super::raster::Canvas;
+
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+pub const SEA_COLOR: [u8; 4] = [0xFF, 0x00, 0x00, 0xFF];
+pub const LAND_COLOR: [u8; 4] = [0x00, 0xFF, 0x00, 0xFF];
+
+pub fn create_world_map(width: u32, height: u32) -> Result<SimpleImage, Box<dyn Error>> {
+    create_world_map_with_colors(width, height, SEA_COLOR, LAND_COLOR)
+}
+
+pub fn create_world_map_with_colors(
+    width: u32,
+    height: u32,
+    sea_color: [u8; 4],
+    land_color: [u8; 4],
+) -> Result<SimpleImage, Box<dyn Error>> {
+    create_projected_world_map(width, height, &Equirectangular, sea_color, land_color)
+}
+
+/// Paints every country in `countries.geojson` onto a `width` x `height` image. When the image
+/// doesn't have the projection's aspect ratio, the map is centered with margins around it.
+/// City markers and clicks on the map should go through the same `projection`.
+pub fn create_projected_world_map(
+    width: u32,
+    height: u32,
+    projection: &dyn Projection,
+    sea_color: [u8; 4],
+    land_color: [u8; 4],
+) -> Result<SimpleImage, Box<dyn Error>> {
+    let countries = load_countries()?;
+
+    let mut world_map = SimpleImage::new(width, height);
+
+    // Draw background
+    println!("Drawing background");
+    let (width_f, height_f) = (width as f64, height as f64);
+    let outline = projection
+        .outline()
+        .into_iter()
+        .map(|x| projection.map_to_image(x, width_f, height_f))
+        .collect::<Vec<_>>();
+    world_map.draw_polygon(&outline, sea_color);
+
+    println!("Drawing {} countries", countries.len());
+    draw_countries(&mut world_map, &countries, projection, land_color, sea_color);
+
+    Ok(world_map)
+}
+
+/// The screen position of every vertex of a ring.
+fn screen_ring(ring: &[Coordinate], projection: &dyn Projection, width: f64, height: f64) -> Vec<Point> {
+    ring.iter()
+        .map(|x| projection.map_to_image(projection.project(*x), width, height))
+        .collect()
+}
+
+/// Fills the countries with `land_color` and their holes, such as lakes, with `hole_color`.
+///
+/// Holes are filled after every country, then the polygons inside holes are filled again,
+/// so an enclave like Lesotho isn't covered by the hole South Africa has for it.
+pub fn draw_countries(
+    image: &mut dyn Canvas,
+    countries: &[CountryShape],
+    projection: &dyn Projection,
+    land_color: [u8; 4],
+    hole_color: [u8; 4],
+) {
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    let screen = |ring: &[Coordinate]| screen_ring(ring, projection, width, height);
+    fill_countries(image, countries, &screen, land_color, hole_color);
+}
+
+/// `draw_countries` with any mapping from rings to screen points. Polygons that end up
+/// entirely outside the image are skipped.
+pub(crate) fn fill_countries(
+    image: &mut dyn Canvas,
+    countries: &[CountryShape],
+    screen: &dyn Fn(&[Coordinate]) -> Vec<Point>,
+    land_color: [u8; 4],
+    hole_color: [u8; 4],
+) {
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    let mut fill = |ring: &[Coordinate], color: [u8; 4]| {
+        let points = screen(ring);
+        let outside = points.iter().all(|p| p.x < 0.0)
+            || points.iter().all(|p| p.x > width)
+            || points.iter().all(|p| p.y < 0.0)
+            || points.iter().all(|p| p.y > height);
+        if !outside {
+            image.draw_polygon(&points, color);
+        }
+    };
+
+    let polygons = countries.iter().flat_map(|x| &x.polygons).collect::<Vec<_>>();
+    for polygon in &polygons {
+        fill(&polygon.exterior, land_color);
+    }
+    for polygon in &polygons {
+        for hole in &polygon.holes {
+            fill(hole, hole_color);
+        }
+    }
+    let with_holes = polygons.iter().filter(|x| !x.holes.is_empty()).collect::<Vec<_>>();
+    for polygon in &polygons {
+        // Not a vertex, an enclave shares those with the hole it fills
+        let Some(point) = polygon.interior_point() else {
+            continue;
+        };
+        if with_holes.iter().any(|x| x.hole_contains(point)) {
+            fill(&polygon.exterior, land_color);
+        }
+    }
+}
+
+/// Draws GeoJSON on top of a map, e.g. rivers, routes or custom regions: polygons are filled,
+/// lines are `line_width` pixels wide and points are squares twice that size.
+pub fn draw_features(
+    image: &mut dyn Canvas,
+    features: &FeatureCollection,
+    projection: &dyn Projection,
+    color: [u8; 4],
+    line_width: f64,
+) {
+    for geometry in features.features.iter().filter_map(|x| x.geometry.as_ref()) {
+        draw_geometry(image, geometry, projection, color, line_width);
+    }
+}
+
+pub fn draw_geometry(
+    image: &mut dyn Canvas,
+    geometry: &Geometry,
+    projection: &dyn Projection,
+    color: [u8; 4],
+    line_width: f64,
+) {
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    let screen = |ring: &[Coordinate]| screen_ring(ring, projection, width, height);
+    match geometry {
+        Geometry::Point(point) => {
+            if let Some(point) = projection.to_screen(*point, width, height) {
+                draw_point(image, point, color, line_width);
+            }
+        }
+        Geometry::MultiPoint(points) => {
+            for point in points.iter().filter_map(|x| projection.to_screen(*x, width, height)) {
+                draw_point(image, point, color, line_width);
+            }
+        }
+        Geometry::LineString(line) => draw_line(image, &screen(line), color, line_width),
+        Geometry::MultiLineString(lines) => {
+            for line in lines {
+                draw_line(image, &screen(line), color, line_width);
+            }
+        }
+        Geometry::Polygon(_) | Geometry::MultiPolygon(_) => {
+            for rings in geometry.polygons() {
+                if let Some(exterior) = rings.first() {
+                    image.draw_polygon(&screen(exterior), color);
+                }
+            }
+        }
+        Geometry::GeometryCollection(geometries) => {
+            for geometry in geometries {
+                draw_geometry(image, geometry, projection, color, line_width);
+            }
+        }
+    }
+}
+
+fn draw_point(image: &mut dyn Canvas, point: Point, color: [u8; 4], size: f64) {
+    image.draw_polygon(&[
+        Point { x: point.x - size, y: point.y - size },
+        Point { x: point.x + size, y: point.y - size },
+        Point { x: point.x + size, y: point.y + size },
+        Point { x: point.x - size, y: point.y + size },
+    ], color);
+}
+
+/// Each segment is drawn as a thin quadrilateral.
+fn draw_line(image: &mut dyn Canvas, points: &[Point], color: [u8; 4], line_width: f64) {
+    for segment in points.windows(2) {
+        let (a, b) = (&segment[0], &segment[1]);
+        let length = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+        if length == 0.0 {
+            continue;
+        }
+        let (nx, ny) = (-(b.y - a.y) / length * line_width / 2.0, (b.x - a.x) / length * line_width / 2.0);
+        image.draw_polygon(&[
+            Point { x: a.x + nx, y: a.y + ny },
+            Point { x: b.x + nx, y: b.y + ny },
+            Point { x: b.x - nx, y: b.y - ny },
+            Point { x: a.x - nx, y: a.y - ny },
+        ], color);
+    }
+}
+
+pub fn draw_image(window: &mut SimpleWindow, image: &SimpleImage, position: (i32, i32), alignment: Alignment) {
+    let image_width = image.width();
+    let image_height = image.height();
+    let y = position.1;
+    let x = match alignment {
+        Alignment::Left => position.0,
+        Alignment::Center => position.0 - (image_width / 2) as i32,
+        Alignment::Right => position.0 - image_width as i32,
+    };
+    let draw_rect = Rect::new(x, y, image_width, image_height);
+    let blend = image_width < window.width() || image_height < window.height();
+    if let Err(error) = window.draw_image(image, Some(draw_rect), blend) {
+        println!("Couldn't draw image: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::countries::parse_countries;
+    use crate::geo::raster::Raster;
+
+    /// A square country with a hole, and an enclave whose ring is the hole's ring from another corner.
+    const ENCLAVE: &str = r#"{
+        "type": "FeatureCollection",
+        "features": [
+            {
+                "type": "Feature",
+                "properties": { "ADMIN": "Squareland" },
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [
+                        [[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]],
+                        [[4, 4], [6, 4], [6, 6], [4, 6], [4, 4]]
+                    ]
+                }
+            },
+            {
+                "type": "Feature",
+                "properties": { "ADMIN": "Enclave" },
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[6, 6], [4, 6], [4, 4], [6, 4], [6, 6]]]
+                }
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_enclave_touching_hole_is_filled() {
+        let countries = parse_countries(ENCLAVE).unwrap();
+        let mut raster = Raster::new(100, 100);
+        let screen = |ring: &[Coordinate]| {
+            ring.iter()
+                .map(|x| Point { x: x.longitude * 10.0, y: (10.0 - x.latitude) * 10.0 })
+                .collect::<Vec<_>>()
+        };
+        fill_countries(&mut raster, &countries, &screen, LAND_COLOR, SEA_COLOR);
+        assert_eq!(raster.pixel(20, 20), LAND_COLOR);
+        assert_eq!(raster.pixel(50, 50), LAND_COLOR);
+    }
+}
@@ -0,0 +1,21 @@
+//! Everything the map exercises share: the city dataset, the country borders and the world map
+//! renderer. The modules under `helpers` re-export from here.
+
+pub mod countries;
+pub mod geojson;
+pub mod map;
+pub mod projection;
+pub mod raster;
+pub mod report;
+pub mod svg;
+pub mod viewport;
+
+pub use crate::dataset::{CityDataset, DatasetError};
+pub use crate::geocode::ReverseGeocoder;
+pub use crate::{load_cities, load_city_data, City, CityData, CityGeometry};
+pub use countries::{load_countries, CountryShape};
+pub use map::{create_world_map, draw_image, Alignment};
+pub use projection::{Equirectangular, Orthographic, Projection, ProjectionKind, Robinson, WebMercator};
+pub use raster::{Canvas, Raster};
+pub use report::{MapReport, Marker};
+pub use viewport::{Viewport, ViewportInput};
@@ -0,0 +1,429 @@
+use std::f64::consts::{FRAC_PI_4, PI};
+use std::str::FromStr;
+
+use apricity::{Coordinate, Point};
+
+/// Maps coordinates to a map and back.
+///
+/// Projections work in map units from 0.0 to 1.0 on both axes, with y growing downwards like
+/// screen coordinates; `to_screen` and `to_coordinate` scale that to an image of a given size,
+/// keeping the map's `aspect_ratio`.
+/// For every visible coordinate, `inverse(project(c))` is `c` again, up to rounding and except
+/// for the longitude at the poles and Web Mercator's latitude cutoff.
+pub trait Projection {
+    /// Where `coordinate` ends up on the map. Coordinates that aren't visible are moved to the
+    /// closest point on the edge of the map, so polygons can still be drawn.
+    fn project(&self, coordinate: Coordinate) -> (f64, f64);
+
+    /// The coordinate at a point on the map, or `None` outside the map.
+    fn inverse(&self, x: f64, y: f64) -> Option<Coordinate>;
+
+    /// Whether `coordinate` is on the map at all, e.g. not on the far side of a globe.
+    fn is_visible(&self, _coordinate: Coordinate) -> bool {
+        true
+    }
+
+    /// Width divided by height of the map without distortion.
+    fn aspect_ratio(&self) -> f64;
+
+    /// The edge of the map, for painting the sea.
+    fn outline(&self) -> Vec<(f64, f64)> {
+        let latitude = |step: i32| (step * 5 - 90) as f64;
+        let west = (0..=36).rev().map(|x| Coordinate { latitude: latitude(x), longitude: -180.0 });
+        let east = (0..=36).map(|x| Coordinate { latitude: latitude(x), longitude: 180.0 });
+        west.chain(east).map(|x| self.project(x)).collect()
+    }
+
+    /// The size of the map on a `width` x `height` image: as large as it fits without
+    /// distortion. The rest of the image is a margin on two opposite sides.
+    fn map_size(&self, width: f64, height: f64) -> (f64, f64) {
+        let aspect_ratio = self.aspect_ratio();
+        if width / height > aspect_ratio {
+            (height * aspect_ratio, height)
+        } else {
+            (width, width / aspect_ratio)
+        }
+    }
+
+    /// Scales a point in map units to a `width` x `height` image, with the map centered on it.
+    fn map_to_image(&self, (x, y): (f64, f64), width: f64, height: f64) -> Point {
+        let (map_width, map_height) = self.map_size(width, height);
+        Point {
+            x: (width - map_width) / 2.0 + x * map_width,
+            y: (height - map_height) / 2.0 + y * map_height,
+        }
+    }
+
+    /// The inverse of `map_to_image`.
+    fn image_to_map(&self, point: Point, width: f64, height: f64) -> (f64, f64) {
+        let (map_width, map_height) = self.map_size(width, height);
+        (
+            (point.x - (width - map_width) / 2.0) / map_width,
+            (point.y - (height - map_height) / 2.0) / map_height,
+        )
+    }
+
+    fn to_screen(&self, coordinate: Coordinate, width: f64, height: f64) -> Option<Point> {
+        self.is_visible(coordinate)
+            .then(|| self.map_to_image(self.project(coordinate), width, height))
+    }
+
+    fn to_coordinate(&self, point: Point, width: f64, height: f64) -> Option<Coordinate> {
+        let (x, y) = self.image_to_map(point, width, height);
+        self.inverse(x, y)
+    }
+}
+
+fn wrap_longitude(longitude: f64) -> f64 {
+    let wrapped = (longitude + 180.0).rem_euclid(360.0) - 180.0;
+    if wrapped == -180.0 && longitude > 0.0 {
+        180.0
+    } else {
+        wrapped
+    }
+}
+
+fn in_unit_square(x: f64, y: f64) -> bool {
+    (0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y)
+}
+
+/// Longitude and latitude as x and y, the same as `Coordinate::screen`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Equirectangular;
+
+impl Projection for Equirectangular {
+    fn project(&self, coordinate: Coordinate) -> (f64, f64) {
+        ((coordinate.longitude + 180.0) / 360.0, (90.0 - coordinate.latitude) / 180.0)
+    }
+
+    fn inverse(&self, x: f64, y: f64) -> Option<Coordinate> {
+        in_unit_square(x, y).then_some(Coordinate {
+            latitude: 90.0 - y * 180.0,
+            longitude: x * 360.0 - 180.0,
+        })
+    }
+
+    fn aspect_ratio(&self) -> f64 {
+        2.0
+    }
+}
+
+/// The square map used by web map tiles. Latitudes beyond ±85.05° are cut off.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WebMercator;
+
+impl WebMercator {
+    pub const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+}
+
+impl Projection for WebMercator {
+    fn project(&self, coordinate: Coordinate) -> (f64, f64) {
+        let latitude = coordinate.latitude.clamp(-Self::MAX_LATITUDE, Self::MAX_LATITUDE).to_radians();
+        let y = (FRAC_PI_4 + latitude / 2.0).tan().ln();
+        ((coordinate.longitude + 180.0) / 360.0, (1.0 - y / PI) / 2.0)
+    }
+
+    fn inverse(&self, x: f64, y: f64) -> Option<Coordinate> {
+        in_unit_square(x, y).then(|| Coordinate {
+            latitude: (PI * (1.0 - 2.0 * y)).sinh().atan().to_degrees(),
+            longitude: x * 360.0 - 180.0,
+        })
+    }
+
+    fn aspect_ratio(&self) -> f64 {
+        1.0
+    }
+}
+
+/// Robinson's compromise projection, from his table of parallel lengths and distances at
+/// 5° steps, linearly interpolated so `inverse` undoes `project` exactly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Robinson;
+
+const ROBINSON_X: [f64; 19] = [
+    1.0000, 0.9986, 0.9954, 0.9900, 0.9822, 0.9730, 0.9600, 0.9427, 0.9216, 0.8962, 0.8679, 0.8350, 0.7986, 0.7597,
+    0.7186, 0.6732, 0.6213, 0.5722, 0.5322,
+];
+const ROBINSON_Y: [f64; 19] = [
+    0.0000, 0.0620, 0.1240, 0.1860, 0.2480, 0.3100, 0.3720, 0.4340, 0.4958, 0.5571, 0.6176, 0.6769, 0.7346, 0.7903,
+    0.8435, 0.8936, 0.9394, 0.9761, 1.0000,
+];
+const ROBINSON_X_SCALE: f64 = 0.8487;
+const ROBINSON_Y_SCALE: f64 = 1.3523;
+const ROBINSON_WIDTH: f64 = ROBINSON_X_SCALE * PI;
+const ROBINSON_HEIGHT: f64 = ROBINSON_Y_SCALE;
+
+impl Robinson {
+    /// The table index and the fraction of the way to the next row for an absolute latitude.
+    fn row(latitude: f64) -> (usize, f64) {
+        let position = (latitude.abs().min(90.0) / 5.0).min(17.999_999);
+        (position as usize, position.fract())
+    }
+
+    fn interpolate(table: &[f64; 19], (index, fraction): (usize, f64)) -> f64 {
+        table[index] + (table[index + 1] - table[index]) * fraction
+    }
+}
+
+impl Projection for Robinson {
+    fn project(&self, coordinate: Coordinate) -> (f64, f64) {
+        let row = Robinson::row(coordinate.latitude);
+        let x = Robinson::interpolate(&ROBINSON_X, row) * coordinate.longitude.to_radians() * ROBINSON_X_SCALE;
+        let y = Robinson::interpolate(&ROBINSON_Y, row) * ROBINSON_Y_SCALE * coordinate.latitude.signum();
+        (0.5 + x / (2.0 * ROBINSON_WIDTH), 0.5 - y / (2.0 * ROBINSON_HEIGHT))
+    }
+
+    fn inverse(&self, x: f64, y: f64) -> Option<Coordinate> {
+        if !in_unit_square(x, y) {
+            return None;
+        }
+        let x = (x - 0.5) * 2.0 * ROBINSON_WIDTH;
+        let y = (0.5 - y) * 2.0 * ROBINSON_HEIGHT;
+        let table_y = (y.abs() / ROBINSON_Y_SCALE).min(1.0);
+        let index = ROBINSON_Y.partition_point(|&row| row <= table_y).clamp(1, 18) - 1;
+        let fraction = (table_y - ROBINSON_Y[index]) / (ROBINSON_Y[index + 1] - ROBINSON_Y[index]);
+        let latitude = (index as f64 + fraction) * 5.0 * y.signum();
+        let longitude = (x / (Robinson::interpolate(&ROBINSON_X, (index, fraction)) * ROBINSON_X_SCALE)).to_degrees();
+        (longitude.abs() <= 180.0).then_some(Coordinate { latitude, longitude })
+    }
+
+    fn aspect_ratio(&self) -> f64 {
+        ROBINSON_WIDTH / ROBINSON_HEIGHT
+    }
+}
+
+/// The earth as a globe seen from far away, centered on `center`. Only half of it is visible.
+#[derive(Clone, Copy, Debug)]
+pub struct Orthographic {
+    pub center: Coordinate,
+}
+
+impl Orthographic {
+    pub fn new(center: Coordinate) -> Orthographic {
+        Orthographic { center }
+    }
+
+    /// The point on a globe of radius 1 centered on 0, 0, and the cosine of the angle from the center.
+    fn globe(&self, coordinate: Coordinate) -> (f64, f64, f64) {
+        let (latitude, center_latitude) = (coordinate.latitude.to_radians(), self.center.latitude.to_radians());
+        let longitude = (coordinate.longitude - self.center.longitude).to_radians();
+        let x = latitude.cos() * longitude.sin();
+        let y = center_latitude.cos() * latitude.sin() - center_latitude.sin() * latitude.cos() * longitude.cos();
+        let cos_angle =
+            center_latitude.sin() * latitude.sin() + center_latitude.cos() * latitude.cos() * longitude.cos();
+        (x, y, cos_angle)
+    }
+}
+
+impl Projection for Orthographic {
+    fn project(&self, coordinate: Coordinate) -> (f64, f64) {
+        let (mut x, mut y, cos_angle) = self.globe(coordinate);
+        if cos_angle < 0.0 {
+            // On the far side: push it out to the horizon
+            let length = x.hypot(y);
+            if length > 0.0 {
+                (x, y) = (x / length, y / length);
+            }
+        }
+        ((x + 1.0) / 2.0, (1.0 - y) / 2.0)
+    }
+
+    fn inverse(&self, x: f64, y: f64) -> Option<Coordinate> {
+        let (x, y) = (x * 2.0 - 1.0, 1.0 - y * 2.0);
+        let distance = x.hypot(y);
+        if distance > 1.0 {
+            return None;
+        }
+        if distance == 0.0 {
+            return Some(self.center);
+        }
+        let angle = distance.asin();
+        let center_latitude = self.center.latitude.to_radians();
+        let latitude =
+            (angle.cos() * center_latitude.sin() + y * angle.sin() * center_latitude.cos() / distance).asin();
+        let longitude = (x * angle.sin())
+            .atan2(distance * angle.cos() * center_latitude.cos() - y * angle.sin() * center_latitude.sin());
+        Some(Coordinate {
+            latitude: latitude.to_degrees(),
+            longitude: wrap_longitude(self.center.longitude + longitude.to_degrees()),
+        })
+    }
+
+    fn is_visible(&self, coordinate: Coordinate) -> bool {
+        self.globe(coordinate).2 >= 0.0
+    }
+
+    fn aspect_ratio(&self) -> f64 {
+        1.0
+    }
+
+    fn outline(&self) -> Vec<(f64, f64)> {
+        (0..72)
+            .map(|i| {
+                let angle = (i as f64 * 5.0).to_radians();
+                ((1.0 + angle.cos()) / 2.0, (1.0 + angle.sin()) / 2.0)
+            })
+            .collect()
+    }
+}
+
+/// The projections that can be picked by name, e.g. with `--projection`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProjectionKind {
+    #[default]
+    Equirectangular,
+    Mercator,
+    Robinson,
+    /// Centered on 0, 0.
+    Orthographic,
+}
+
+impl ProjectionKind {
+    pub const ALL: [ProjectionKind; 4] = [
+        ProjectionKind::Equirectangular,
+        ProjectionKind::Mercator,
+        ProjectionKind::Robinson,
+        ProjectionKind::Orthographic,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ProjectionKind::Equirectangular => "equirectangular",
+            ProjectionKind::Mercator => "mercator",
+            ProjectionKind::Robinson => "robinson",
+            ProjectionKind::Orthographic => "orthographic",
+        }
+    }
+
+    pub fn projection(self) -> Box<dyn Projection> {
+        match self {
+            ProjectionKind::Equirectangular => Box::new(Equirectangular),
+            ProjectionKind::Mercator => Box::new(WebMercator),
+            ProjectionKind::Robinson => Box::new(Robinson),
+            ProjectionKind::Orthographic => Box::new(Orthographic::new(Coordinate { latitude: 0.0, longitude: 0.0 })),
+        }
+    }
+}
+
+impl FromStr for ProjectionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ProjectionKind::ALL
+            .into_iter()
+            .find(|x| x.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown projection {:?}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(latitude: f64, longitude: f64) -> Coordinate {
+        Coordinate { latitude, longitude }
+    }
+
+    fn assert_round_trip(projection: &dyn Projection, name: &str, max_latitude: i32) {
+        for latitude in (-max_latitude..=max_latitude).step_by(7) {
+            for longitude in (-179..=179).step_by(11) {
+                let coordinate = at(latitude as f64, longitude as f64);
+                if !projection.is_visible(coordinate) {
+                    continue;
+                }
+                let (x, y) = projection.project(coordinate);
+                let back = projection
+                    .inverse(x, y)
+                    .unwrap_or_else(|| panic!("{}: {:?} projected off the map", name, coordinate));
+                assert!(
+                    (back.latitude - coordinate.latitude).abs() < 1e-6
+                        && (back.longitude - coordinate.longitude).abs() < 1e-6,
+                    "{}: {:?} came back as {:?}",
+                    name,
+                    coordinate,
+                    back
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_round_trip(&Equirectangular, "equirectangular", 89);
+        assert_round_trip(&WebMercator, "mercator", 84);
+        assert_round_trip(&Robinson, "robinson", 89);
+        assert_round_trip(&Orthographic::new(at(59.3, 18.1)), "orthographic", 89);
+        assert_round_trip(&Orthographic::new(at(-33.9, 151.2)), "orthographic", 89);
+    }
+
+    #[test]
+    fn test_projection_kind() {
+        for kind in ProjectionKind::ALL {
+            assert_eq!(kind.name().parse::<ProjectionKind>(), Ok(kind));
+        }
+        assert_eq!("Mercator".parse::<ProjectionKind>(), Ok(ProjectionKind::Mercator));
+        assert!("mollweide".parse::<ProjectionKind>().is_err());
+        assert_eq!(ProjectionKind::Mercator.projection().aspect_ratio(), 1.0);
+    }
+
+    /// The width and height on a 1500 x 750 image of a small circle around `center`.
+    fn circle_size(projection: &dyn Projection, center: Coordinate) -> (f64, f64) {
+        let points = (0..36)
+            .map(|i| {
+                let angle = (i as f64 * 10.0).to_radians();
+                let coordinate = at(center.latitude + angle.sin() * 0.1, center.longitude + angle.cos() * 0.1);
+                projection.to_screen(coordinate, 1500.0, 750.0).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let extent = |value: fn(&Point) -> f64| {
+            let values = points.iter().map(value);
+            values.clone().fold(f64::MIN, f64::max) - values.fold(f64::MAX, f64::min)
+        };
+        (extent(|p| p.x), extent(|p| p.y))
+    }
+
+    #[test]
+    fn test_circle_stays_round() {
+        // Near the equator these projections don't distort shapes, whatever the image size
+        let projections: [(&dyn Projection, &str); 3] = [
+            (&Equirectangular, "equirectangular"),
+            (&WebMercator, "mercator"),
+            (&Orthographic::new(at(0.0, 0.0)), "orthographic"),
+        ];
+        for (projection, name) in projections {
+            let (width, height) = circle_size(projection, at(0.0, 0.0));
+            assert!((width / height - 1.0).abs() < 1e-3, "{}: {} x {}", name, width, height);
+        }
+
+        // The globe is a circle in the middle of the image, not an ellipse across all of it
+        let globe = Orthographic::new(at(0.0, 0.0));
+        for (x, y) in globe.outline() {
+            let point = globe.map_to_image((x, y), 1500.0, 750.0);
+            assert!(((point.x - 750.0).hypot(point.y - 375.0) - 375.0).abs() < 1e-9);
+        }
+        assert!(globe.to_coordinate(Point { x: 100.0, y: 375.0 }, 1500.0, 750.0).is_none());
+    }
+
+    #[test]
+    fn test_equirectangular_matches_screen() {
+        let coordinate = at(59.33, 18.07);
+        let point = Equirectangular.to_screen(coordinate, 1500.0, 750.0).unwrap();
+        let expected = coordinate.screen(1500.0, 750.0);
+        assert!((point.x - expected.x).abs() < 1e-9 && (point.y - expected.y).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_known_points() {
+        let (x, y) = WebMercator.project(at(WebMercator::MAX_LATITUDE, 180.0));
+        assert!((x - 1.0).abs() < 1e-9 && y.abs() < 1e-9);
+        let (x, y) = Robinson.project(at(0.0, 180.0));
+        assert!((x - 1.0).abs() < 1e-9 && (y - 0.5).abs() < 1e-9);
+        assert_eq!(Robinson.inverse(0.99, 0.01).map(|x| x.latitude), None);
+
+        let globe = Orthographic::new(at(0.0, 0.0));
+        assert!(globe.is_visible(at(10.0, 80.0)));
+        assert!(!globe.is_visible(at(0.0, 100.0)));
+        assert!(globe.to_screen(at(0.0, 100.0), 500.0, 500.0).is_none());
+        assert_eq!(globe.inverse(0.0, 0.0).map(|x| x.latitude), None);
+    }
+}
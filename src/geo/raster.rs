@@ -0,0 +1,255 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use apricity::gui::SimpleImage;
+use apricity::Point;
+use rusttype::{point, Font, Scale};
+
+/// Something polygons can be painted onto. The map drawing functions take a `Canvas` so the
+/// same code draws into a window's `SimpleImage` and into a headless `Raster`.
+pub trait Canvas {
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+    fn draw_polygon(&mut self, vertices: &[Point], color: [u8; 4]);
+}
+
+impl Canvas for SimpleImage {
+    fn width(&self) -> u32 {
+        SimpleImage::width(self)
+    }
+
+    fn height(&self) -> u32 {
+        SimpleImage::height(self)
+    }
+
+    fn draw_polygon(&mut self, vertices: &[Point], color: [u8; 4]) {
+        SimpleImage::draw_polygon(self, vertices, color)
+    }
+}
+
+/// The font labels are drawn with, or `None` if the bundled font can't be read.
+pub fn default_font() -> Option<Font<'static>> {
+    Font::try_from_bytes(ttf_noto_sans::REGULAR)
+}
+
+/// An RGBA image in memory that doesn't need a window, for rendering maps to files.
+#[derive(Clone, Debug)]
+pub struct Raster {
+    width: u32,
+    height: u32,
+    /// Rows from the top, four bytes per pixel.
+    pixels: Vec<u8>,
+}
+
+impl Raster {
+    /// A fully transparent image.
+    pub fn new(width: u32, height: u32) -> Raster {
+        Raster {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    /// Paints `color` over a pixel, weighted by `coverage` from 0.0 to 1.0.
+    fn blend(&mut self, x: i32, y: i32, color: [u8; 4], coverage: f32) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        let alpha = color[3] as f32 / 255.0 * coverage.clamp(0.0, 1.0);
+        let destination_alpha = self.pixels[i + 3] as f32 / 255.0;
+        let out_alpha = alpha + destination_alpha * (1.0 - alpha);
+        if out_alpha <= 0.0 {
+            return;
+        }
+        for (destination, source) in self.pixels[i..i + 3].iter_mut().zip(color) {
+            let mixed = source as f32 * alpha + *destination as f32 * destination_alpha * (1.0 - alpha);
+            *destination = (mixed / out_alpha).round() as u8;
+        }
+        self.pixels[i + 3] = (out_alpha * 255.0).round() as u8;
+    }
+
+    /// Fills everything, e.g. with a background color.
+    pub fn clear(&mut self, color: [u8; 4]) {
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    /// Fills the pixels whose centers are inside the polygon, using the even-odd rule.
+    pub fn fill_polygon(&mut self, vertices: &[Point], color: [u8; 4]) {
+        if vertices.len() < 3 {
+            return;
+        }
+        let top = vertices.iter().map(|p| p.y).fold(f64::INFINITY, f64::min).floor().max(0.0) as i32;
+        let bottom = vertices.iter().map(|p| p.y).fold(f64::NEG_INFINITY, f64::max).ceil().min(self.height as f64) as i32;
+        let mut crossings = Vec::new();
+        for y in top..bottom {
+            let center = y as f64 + 0.5;
+            crossings.clear();
+            for (i, a) in vertices.iter().enumerate() {
+                let b = &vertices[(i + 1) % vertices.len()];
+                if (a.y <= center) != (b.y <= center) {
+                    crossings.push(a.x + (center - a.y) / (b.y - a.y) * (b.x - a.x));
+                }
+            }
+            crossings.sort_by(f64::total_cmp);
+            for span in crossings.chunks_exact(2) {
+                let start = (span[0] - 0.5).ceil().max(0.0) as i32;
+                let end = (span[1] - 0.5).ceil().min(self.width as f64) as i32;
+                for x in start..end {
+                    self.blend(x, y, color, 1.0);
+                }
+            }
+        }
+    }
+
+    pub fn fill_circle(&mut self, center: Point, radius: f64, color: [u8; 4]) {
+        self.fill_polygon(&circle(center, radius), color);
+    }
+
+    /// A ring `line_width` pixels wide, centered on `radius`.
+    pub fn stroke_circle(&mut self, center: Point, radius: f64, line_width: f64, color: [u8; 4]) {
+        // Both circles closed and joined by the same edge there and back, which cancels out
+        // under the even-odd rule and leaves the inner circle as a hole
+        let mut ring = Vec::new();
+        for radius in [radius + line_width / 2.0, (radius - line_width / 2.0).max(0.0)] {
+            let circle = circle(center, radius);
+            ring.extend_from_slice(&circle);
+            ring.push(circle[0]);
+        }
+        self.fill_polygon(&ring, color);
+    }
+
+    /// Draws `text` with its top left corner at `position`.
+    pub fn draw_text(&mut self, font: &Font, text: &str, position: Point, size: f32, color: [u8; 4]) {
+        let scale = Scale::uniform(size);
+        let ascent = font.v_metrics(scale).ascent;
+        let start = point(position.x as f32, position.y as f32 + ascent);
+        for glyph in font.layout(text, scale, start) {
+            if let Some(bounds) = glyph.pixel_bounding_box() {
+                glyph.draw(|x, y, coverage| {
+                    self.blend(bounds.min.x + x as i32, bounds.min.y + y as i32, color, coverage);
+                });
+            }
+        }
+    }
+
+    pub fn write_png(&self, out: impl Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&self.pixels).map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl Canvas for Raster {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn draw_polygon(&mut self, vertices: &[Point], color: [u8; 4]) {
+        self.fill_polygon(vertices, color)
+    }
+}
+
+/// How wide `text` is in pixels when drawn at `size`.
+pub fn text_width(font: &Font, text: &str, size: f32) -> f64 {
+    let scale = Scale::uniform(size);
+    font.layout(text, scale, point(0.0, 0.0))
+        .last()
+        .map_or(0.0, |x| (x.position().x + x.unpositioned().h_metrics().advance_width) as f64)
+}
+
+fn circle(center: Point, radius: f64) -> Vec<Point> {
+    let segments = (radius * 2.0).clamp(12.0, 90.0) as usize;
+    (0..segments)
+        .map(|i| {
+            let angle = i as f64 / segments as f64 * std::f64::consts::TAU;
+            Point {
+                x: center.x + radius * angle.cos(),
+                y: center.y + radius * angle.sin(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [0xFF, 0x00, 0x00, 0xFF];
+
+    #[test]
+    fn test_fill_polygon() {
+        let mut raster = Raster::new(10, 10);
+        raster.clear([0, 0, 0xFF, 0xFF]);
+        raster.fill_polygon(&[
+            Point { x: 2.0, y: 2.0 },
+            Point { x: 6.0, y: 2.0 },
+            Point { x: 6.0, y: 6.0 },
+            Point { x: 2.0, y: 6.0 },
+        ], RED);
+        let filled = (0..10)
+            .flat_map(|y| (0..10).map(move |x| (x, y)))
+            .filter(|&(x, y)| raster.pixel(x, y) == RED)
+            .count();
+        assert_eq!(filled, 16);
+        assert_eq!(raster.pixel(2, 2), RED);
+        assert_eq!(raster.pixel(6, 6), [0, 0, 0xFF, 0xFF]);
+
+        // Half transparent red over blue
+        raster.fill_polygon(&[
+            Point { x: 0.0, y: 8.0 },
+            Point { x: 10.0, y: 8.0 },
+            Point { x: 10.0, y: 10.0 },
+        ], [0xFF, 0x00, 0x00, 0x80]);
+        assert_eq!(raster.pixel(9, 9), [0x80, 0x00, 0x7F, 0xFF]);
+    }
+
+    #[test]
+    fn test_stroke_circle_leaves_center() {
+        let mut raster = Raster::new(40, 40);
+        raster.stroke_circle(Point { x: 20.0, y: 20.0 }, 10.0, 2.0, RED);
+        assert_eq!(raster.pixel(20, 20)[3], 0);
+        // All the way around, including where the circle starts and ends
+        assert_eq!(raster.pixel(30, 20), RED);
+        assert_eq!(raster.pixel(30, 19), RED);
+        assert_eq!(raster.pixel(9, 20), RED);
+    }
+
+    #[test]
+    fn test_write_png() {
+        let mut raster = Raster::new(3, 2);
+        raster.clear(RED);
+        let mut bytes = Vec::new();
+        raster.write_png(&mut bytes).unwrap();
+
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(&pixels[..info.buffer_size()], raster.pixels());
+    }
+}
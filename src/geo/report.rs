@@ -0,0 +1,364 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use apricity::{Coordinate, Point};
+use rusttype::Font;
+
+use super::countries::CountryShape;
+use super::map::{draw_countries, LAND_COLOR, SEA_COLOR};
+use super::projection::{Equirectangular, Projection};
+use super::raster::{default_font, text_width, Raster};
+use super::svg::{country_path, escape, paint, path_data};
+use crate::CityData;
+
+pub const MARKER_RADIUS: f64 = 4.0;
+pub const LABEL_SIZE: f32 = 14.0;
+pub const TITLE_SIZE: f32 = 28.0;
+const LEGEND_LINE_HEIGHT: f64 = LABEL_SIZE as f64 * 1.5;
+const TEXT_COLOR: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+const LEGEND_BACKGROUND: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xD0];
+
+/// A dot on the map, with an optional label to the right of it.
+#[derive(Clone, Debug)]
+pub struct Marker {
+    pub coordinate: Coordinate,
+    pub label: Option<String>,
+    pub color: [u8; 4],
+    pub radius: f64,
+}
+
+impl Marker {
+    pub fn new(coordinate: Coordinate, color: [u8; 4]) -> Marker {
+        Marker {
+            coordinate,
+            label: None,
+            color,
+            radius: MARKER_RADIUS,
+        }
+    }
+
+    /// A marker labelled with the city's name.
+    pub fn city(city: &CityData, color: [u8; 4]) -> Marker {
+        Marker {
+            label: Some(city.name.clone()),
+            ..Marker::new(city.coordinates, color)
+        }
+    }
+
+    /// A labelled marker with its area in proportion to the city's population.
+    pub fn city_by_population(city: &CityData, color: [u8; 4]) -> Marker {
+        Marker {
+            radius: population_radius(city.population),
+            ..Marker::city(city, color)
+        }
+    }
+}
+
+/// `MARKER_RADIUS` for a city of 100 000, growing with the square root of the population,
+/// between 2 and 24 pixels.
+pub fn population_radius(population: i64) -> f64 {
+    (MARKER_RADIUS * (population.max(0) as f64 / 100_000.0).sqrt()).clamp(2.0, 24.0)
+}
+
+/// A world map with markers, a title and a legend, rendered without a window to PNG or SVG.
+///
+/// ```no_run
+/// # use rustdemo::geo::{load_countries, report::{MapReport, Marker}};
+/// # use apricity::Coordinate;
+/// let red = [0xFF, 0x00, 0x00, 0xFF];
+/// MapReport::new(1500, 750)
+///     .title("Stockholm")
+///     .marker(Marker::new(Coordinate { latitude: 59.33, longitude: 18.07 }, red))
+///     .legend("Capital", red)
+///     .save_png(&load_countries()?, "stockholm.png")?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct MapReport {
+    width: u32,
+    height: u32,
+    projection: Box<dyn Projection>,
+    sea_color: [u8; 4],
+    land_color: [u8; 4],
+    title: Option<String>,
+    markers: Vec<Marker>,
+    legend: Vec<(String, [u8; 4])>,
+    /// Without a font, markers and legend swatches are still drawn but the text isn't.
+    font: Option<Font<'static>>,
+}
+
+impl MapReport {
+    pub fn new(width: u32, height: u32) -> MapReport {
+        MapReport {
+            width,
+            height,
+            projection: Box::new(Equirectangular),
+            sea_color: SEA_COLOR,
+            land_color: LAND_COLOR,
+            title: None,
+            markers: Vec::new(),
+            legend: Vec::new(),
+            font: default_font(),
+        }
+    }
+
+    pub fn projection(mut self, projection: impl Projection + 'static) -> MapReport {
+        self.projection = Box::new(projection);
+        self
+    }
+
+    pub fn colors(mut self, sea_color: [u8; 4], land_color: [u8; 4]) -> MapReport {
+        self.sea_color = sea_color;
+        self.land_color = land_color;
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> MapReport {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn marker(mut self, marker: Marker) -> MapReport {
+        self.markers.push(marker);
+        self
+    }
+
+    pub fn markers(mut self, markers: impl IntoIterator<Item = Marker>) -> MapReport {
+        self.markers.extend(markers);
+        self
+    }
+
+    /// Adds a line to the legend in the bottom left corner.
+    pub fn legend(mut self, label: &str, color: [u8; 4]) -> MapReport {
+        self.legend.push((label.to_string(), color));
+        self
+    }
+
+    fn outline(&self) -> Vec<Point> {
+        let (width, height) = (self.width as f64, self.height as f64);
+        self.projection
+            .outline()
+            .into_iter()
+            .map(|x| self.projection.map_to_image(x, width, height))
+            .collect()
+    }
+
+    /// The markers the projection can show, with their positions on the map.
+    fn visible_markers(&self) -> Vec<(&Marker, Point)> {
+        let (width, height) = (self.width as f64, self.height as f64);
+        self.markers
+            .iter()
+            .filter_map(|x| Some((x, self.projection.to_screen(x.coordinate, width, height)?)))
+            .collect()
+    }
+
+    /// Measured with the font when there is one, otherwise an estimate.
+    fn label_width(&self, label: &str) -> f64 {
+        match &self.font {
+            Some(font) => text_width(font, label, LABEL_SIZE),
+            None => label.chars().count() as f64 * LABEL_SIZE as f64 * 0.6,
+        }
+    }
+
+    /// The left, top, width and height of the legend.
+    fn legend_box(&self) -> (f64, f64, f64, f64) {
+        let label_width = self.legend.iter().map(|(label, _)| self.label_width(label)).fold(0.0, f64::max);
+        let (width, height) = (label_width + 40.0, self.legend.len() as f64 * LEGEND_LINE_HEIGHT + 10.0);
+        (10.0, self.height as f64 - height - 10.0, width, height)
+    }
+
+    pub fn render(&self, countries: &[CountryShape]) -> Raster {
+        let mut raster = Raster::new(self.width, self.height);
+        raster.fill_polygon(&self.outline(), self.sea_color);
+        draw_countries(&mut raster, countries, self.projection.as_ref(), self.land_color, self.sea_color);
+
+        // Dots first so no label is hidden under a neighbour's dot
+        let visible = self.visible_markers();
+        for (marker, point) in &visible {
+            raster.fill_circle(*point, marker.radius, marker.color);
+            raster.stroke_circle(*point, marker.radius, 1.0, TEXT_COLOR);
+        }
+        if let Some(font) = &self.font {
+            for (marker, point) in &visible {
+                if let Some(label) = &marker.label {
+                    let position = Point {
+                        x: point.x + marker.radius + 3.0,
+                        y: point.y - LABEL_SIZE as f64 / 2.0,
+                    };
+                    raster.draw_text(font, label, position, LABEL_SIZE, TEXT_COLOR);
+                }
+            }
+            if let Some(title) = &self.title {
+                raster.draw_text(font, title, Point { x: 10.0, y: 10.0 }, TITLE_SIZE, TEXT_COLOR);
+            }
+        }
+        self.draw_legend(&mut raster);
+        raster
+    }
+
+    fn draw_legend(&self, raster: &mut Raster) {
+        if self.legend.is_empty() {
+            return;
+        }
+        let (left, top, box_width, box_height) = self.legend_box();
+        raster.fill_polygon(&[
+            Point { x: left, y: top },
+            Point { x: left + box_width, y: top },
+            Point { x: left + box_width, y: top + box_height },
+            Point { x: left, y: top + box_height },
+        ], LEGEND_BACKGROUND);
+
+        for (i, (label, color)) in self.legend.iter().enumerate() {
+            let middle = top + 5.0 + (i as f64 + 0.5) * LEGEND_LINE_HEIGHT;
+            let swatch = Point { x: left + 15.0, y: middle };
+            raster.fill_circle(swatch, MARKER_RADIUS, *color);
+            raster.stroke_circle(swatch, MARKER_RADIUS, 1.0, TEXT_COLOR);
+            if let Some(font) = &self.font {
+                let position = Point { x: left + 30.0, y: middle - LABEL_SIZE as f64 / 2.0 };
+                raster.draw_text(font, label, position, LABEL_SIZE, TEXT_COLOR);
+            }
+        }
+    }
+
+    pub fn save_png(&self, countries: &[CountryShape], path: impl AsRef<Path>) -> io::Result<()> {
+        self.render(countries).save_png(path)
+    }
+
+    /// The same map as `render`, as SVG. Each country is a `<path>` with its ISO code as id,
+    /// inside `<g id="countries">`, and each marker a `<circle>` inside `<g id="cities">`.
+    pub fn write_svg(&self, countries: &[CountryShape], mut out: impl Write) -> io::Result<()> {
+        let (width, height) = (self.width as f64, self.height as f64);
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+            self.width, self.height,
+        )?;
+        writeln!(out, r#"<path id="sea" {} d="{}"/>"#, paint("fill", self.sea_color), path_data([self.outline()]))?;
+
+        writeln!(out, r#"<g id="countries" {}>"#, paint("fill", self.land_color))?;
+        for country in countries {
+            writeln!(out, "{}", country_path(country, self.projection.as_ref(), width, height))?;
+        }
+        writeln!(out, "</g>")?;
+
+        let visible = self.visible_markers();
+        writeln!(out, r#"<g id="cities" {} stroke-width="1">"#, paint("stroke", TEXT_COLOR))?;
+        for (marker, point) in &visible {
+            write!(out, r#"<circle cx="{:.2}" cy="{:.2}" r="{:.2}" {}"#, point.x, point.y, marker.radius, paint("fill", marker.color))?;
+            match &marker.label {
+                Some(label) => writeln!(out, "><title>{}</title></circle>", escape(label))?,
+                None => writeln!(out, "/>")?,
+            }
+        }
+        writeln!(out, "</g>")?;
+
+        writeln!(out, r#"<g id="labels" font-family="sans-serif" font-size="{}" {}>"#, LABEL_SIZE, paint("fill", TEXT_COLOR))?;
+        for (marker, point) in &visible {
+            if let Some(label) = &marker.label {
+                let x = point.x + marker.radius + 3.0;
+                writeln!(out, r#"<text x="{:.2}" y="{:.2}" dominant-baseline="central">{}</text>"#, x, point.y, escape(label))?;
+            }
+        }
+        writeln!(out, "</g>")?;
+
+        if let Some(title) = &self.title {
+            writeln!(
+                out,
+                r#"<text id="title" x="10" y="10" font-family="sans-serif" font-size="{}" dominant-baseline="hanging" {}>{}</text>"#,
+                TITLE_SIZE,
+                paint("fill", TEXT_COLOR),
+                escape(title),
+            )?;
+        }
+
+        if !self.legend.is_empty() {
+            let (left, top, box_width, box_height) = self.legend_box();
+            writeln!(out, r#"<g id="legend" font-family="sans-serif" font-size="{}">"#, LABEL_SIZE)?;
+            writeln!(
+                out,
+                r#"<rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" {}/>"#,
+                left, top, box_width, box_height, paint("fill", LEGEND_BACKGROUND),
+            )?;
+            for (i, (label, color)) in self.legend.iter().enumerate() {
+                let middle = top + 5.0 + (i as f64 + 0.5) * LEGEND_LINE_HEIGHT;
+                writeln!(
+                    out,
+                    r#"<circle cx="{:.2}" cy="{:.2}" r="{}" {} {}/>"#,
+                    left + 15.0, middle, MARKER_RADIUS, paint("fill", *color), paint("stroke", TEXT_COLOR),
+                )?;
+                writeln!(
+                    out,
+                    r#"<text x="{:.2}" y="{:.2}" dominant-baseline="central" {}>{}</text>"#,
+                    left + 30.0, middle, paint("fill", TEXT_COLOR), escape(label),
+                )?;
+            }
+            writeln!(out, "</g>")?;
+        }
+        writeln!(out, "</svg>")
+    }
+
+    pub fn save_svg(&self, countries: &[CountryShape], path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_svg(countries, &mut out)?;
+        out.flush()
+    }
+
+    /// SVG if the file name ends in `.svg`, otherwise PNG.
+    pub fn save(&self, countries: &[CountryShape], path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        match path.extension().and_then(|x| x.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("svg") => self.save_svg(countries, path),
+            _ => self.save_png(countries, path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::countries::parse_countries;
+    use crate::geo::countries::tests::COUNTRIES;
+
+    #[test]
+    fn test_render_report() {
+        let countries = parse_countries(COUNTRIES).unwrap();
+        let blue = [0x00, 0x00, 0xFF, 0xFF];
+        let raster = MapReport::new(360, 180)
+            .marker(Marker::new(Coordinate { latitude: 0.0, longitude: 0.0 }, blue))
+            .legend("Origin", blue)
+            .render(&countries);
+
+        // Equirectangular at one pixel per degree, so the marker is at (180, 90)
+        assert_eq!(raster.pixel(180, 90), blue);
+        assert_eq!(raster.pixel(359, 0), SEA_COLOR);
+        assert_eq!(raster.pixel(188, 82), LAND_COLOR);
+        assert_eq!(raster.pixel(185, 85), SEA_COLOR, "the hole in Squareland");
+        // The legend box is drawn over the bottom left
+        assert_ne!(raster.pixel(12, 160), SEA_COLOR);
+    }
+
+    #[test]
+    fn test_write_svg() {
+        let countries = parse_countries(COUNTRIES).unwrap();
+        let report = MapReport::new(360, 180)
+            .title("Cities <& towns>")
+            .marker(Marker {
+                label: Some("Origin".to_string()),
+                radius: population_radius(400_000),
+                ..Marker::new(Coordinate { latitude: 0.0, longitude: 0.0 }, [0x00, 0x00, 0xFF, 0xFF])
+            })
+            .legend("Cities", [0x00, 0x00, 0xFF, 0xFF]);
+        let mut svg = Vec::new();
+        report.write_svg(&countries, &mut svg).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"360\" height=\"180\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains("<path id=\"SQR\""));
+        // Islands has no alpha-2 code but does have an alpha-3 one
+        assert!(svg.contains("<path id=\"ISL\""));
+        assert!(svg.contains("<circle cx=\"180.00\" cy=\"90.00\" r=\"8.00\" fill=\"#0000ff\"><title>Origin</title></circle>"));
+        assert!(svg.contains(">Cities &lt;&amp; towns&gt;</text>"));
+        assert_eq!(svg.matches("<g").count(), svg.matches("</g>").count());
+    }
+}
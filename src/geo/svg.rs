@@ -0,0 +1,116 @@
+use std::fmt::Write as _;
+
+use apricity::{Coordinate, Point};
+
+use super::countries::CountryShape;
+use super::projection::Projection;
+
+/// Escapes text for use in SVG content and attribute values.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The `fill` or `stroke` attribute and, unless the color is opaque, its opacity, e.g.
+/// `fill="#ff0000" fill-opacity="0.50"`.
+pub fn paint(attribute: &str, color: [u8; 4]) -> String {
+    let [r, g, b, a] = color;
+    let mut paint = format!("{}=\"#{:02x}{:02x}{:02x}\"", attribute, r, g, b);
+    if a != 0xFF {
+        write!(paint, " {}-opacity=\"{:.2}\"", attribute, a as f64 / 255.0).unwrap();
+    }
+    paint
+}
+
+/// Path data for closed outlines, e.g. `M 0.00 0.00 L 10.00 0.00 L 10.00 10.00 Z`.
+pub fn path_data(rings: impl IntoIterator<Item = Vec<Point>>) -> String {
+    let mut data = String::new();
+    for ring in rings {
+        for (i, point) in ring.iter().enumerate() {
+            let command = if i == 0 { 'M' } else { 'L' };
+            if !data.is_empty() {
+                data.push(' ');
+            }
+            write!(data, "{} {:.2} {:.2}", command, point.x, point.y).unwrap();
+        }
+        if !ring.is_empty() {
+            data.push_str(" Z");
+        }
+    }
+    data
+}
+
+/// An id for the country's `<path>`: its ISO 3166 alpha-3 code, or alpha-2, or if it has
+/// neither, its name with everything but letters and digits replaced by `-`.
+pub fn country_id(country: &CountryShape) -> String {
+    match country.iso_a3.as_ref().or(country.iso_a2.as_ref()) {
+        Some(code) => code.clone(),
+        None => country
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect(),
+    }
+}
+
+/// A country as one `<path>`, with every polygon and hole in it. Holes are left unfilled
+/// through `fill-rule="evenodd"` on the path.
+pub fn country_path(country: &CountryShape, projection: &dyn Projection, width: f64, height: f64) -> String {
+    let screen = |ring: &[Coordinate]| {
+        ring.iter()
+            .map(|x| projection.map_to_image(projection.project(*x), width, height))
+            .collect::<Vec<_>>()
+    };
+    let rings = country
+        .polygons
+        .iter()
+        .flat_map(|x| std::iter::once(&x.exterior).chain(&x.holes))
+        .map(|x| screen(x));
+    format!(
+        "<path id=\"{}\" fill-rule=\"evenodd\" d=\"{}\"><title>{}</title></path>",
+        escape(&country_id(country)),
+        path_data(rings),
+        escape(&country.name),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::countries::parse_countries;
+    use crate::geo::countries::tests::COUNTRIES;
+    use crate::geo::projection::Equirectangular;
+
+    #[test]
+    fn test_country_path() {
+        let countries = parse_countries(COUNTRIES).unwrap();
+        let path = country_path(&countries[0], &Equirectangular, 360.0, 180.0);
+        assert!(path.starts_with("<path id=\"SQR\" fill-rule=\"evenodd\" d=\"M 180.00 90.00 L 190.00 90.00 L 190.00 80.00"));
+        assert!(path.ends_with("<title>Squareland</title></path>"));
+        // The outline and the hole
+        assert_eq!(path.matches('M').count(), 2);
+        assert_eq!(path.matches(" Z").count(), 2);
+
+        let mut islands = countries[1].clone();
+        islands.iso_a3 = None;
+        islands.name = "Islands & Co".to_string();
+        assert_eq!(country_id(&islands), "Islands---Co");
+    }
+
+    #[test]
+    fn test_escape_and_paint() {
+        assert_eq!(escape("<a href=\"x\">R&D's</a>"), "&lt;a href=&quot;x&quot;&gt;R&amp;D&apos;s&lt;/a&gt;");
+        assert_eq!(paint("fill", [0xFF, 0x80, 0x00, 0xFF]), "fill=\"#ff8000\"");
+        assert_eq!(paint("stroke", [0, 0, 0, 0x80]), "stroke=\"#000000\" stroke-opacity=\"0.50\"");
+    }
+}
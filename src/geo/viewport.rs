@@ -0,0 +1,316 @@
+use apricity::gui::{Event, MouseButton, SimpleImage};
+use apricity::{Coordinate, Point};
+
+use super::countries::CountryShape;
+use super::map::fill_countries;
+use super::projection::Projection;
+
+pub const MIN_ZOOM: f64 = 1.0;
+pub const MAX_ZOOM: f64 = 64.0;
+/// How much one notch of the mouse wheel zooms.
+pub const ZOOM_STEP: f64 = 1.25;
+/// How far, in pixels, the mouse has to move with the button down before it's a drag
+/// instead of a click.
+pub const DRAG_THRESHOLD: f64 = 4.0;
+
+/// What a window event did to the viewport.
+#[derive(Clone, Copy, Debug)]
+pub enum ViewportInput {
+    /// Nothing, or not an event the viewport handles.
+    Ignored,
+    /// The view was panned or zoomed, so the map needs to be rendered again.
+    Moved,
+    /// The left button was pressed and released without dragging.
+    Click(Point),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Drag {
+    start: (i32, i32),
+    last: (i32, i32),
+    moved: bool,
+}
+
+/// The part of a projected map that's shown in a window: a center and a zoom level.
+///
+/// At zoom 1 the whole map fits in the window, centered and keeping the projection's aspect
+/// ratio. Zooming and panning is done in the projection's map units, so everything drawn
+/// through the same viewport lines up.
+pub struct Viewport {
+    projection: Box<dyn Projection>,
+    width: f64,
+    height: f64,
+    /// The size of the whole map in pixels at zoom 1.
+    map_size: (f64, f64),
+    /// In map units, from 0.0 to 1.0.
+    center: (f64, f64),
+    zoom: f64,
+    mouse: (i32, i32),
+    drag: Option<Drag>,
+}
+
+impl Viewport {
+    pub fn new(projection: impl Projection + 'static, width: u32, height: u32) -> Viewport {
+        Viewport::with_projection(Box::new(projection), width, height)
+    }
+
+    /// `new` with a projection picked at runtime, e.g. from `ProjectionKind::projection`.
+    pub fn with_projection(projection: Box<dyn Projection>, width: u32, height: u32) -> Viewport {
+        let (width, height) = (width as f64, height as f64);
+        Viewport {
+            map_size: projection.map_size(width, height),
+            projection,
+            width,
+            height,
+            center: (0.5, 0.5),
+            zoom: MIN_ZOOM,
+            mouse: (width as i32 / 2, height as i32 / 2),
+            drag: None,
+        }
+    }
+
+    pub fn projection(&self) -> &dyn Projection {
+        self.projection.as_ref()
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    pub fn set_zoom(&mut self, zoom: f64) {
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.clamp_center();
+    }
+
+    pub fn center(&self) -> Option<Coordinate> {
+        self.projection.inverse(self.center.0, self.center.1)
+    }
+
+    pub fn set_center(&mut self, center: Coordinate) {
+        self.center = self.projection.project(center);
+        self.clamp_center();
+    }
+
+    /// Keeps the map covering the window, or centered along an axis where it's smaller than the window.
+    fn clamp_center(&mut self) {
+        let clamp = |center: f64, window: f64, map: f64| {
+            let margin = window / (2.0 * map);
+            if margin >= 0.5 {
+                0.5
+            } else {
+                center.clamp(margin, 1.0 - margin)
+            }
+        };
+        self.center.0 = clamp(self.center.0, self.width, self.map_size.0 * self.zoom);
+        self.center.1 = clamp(self.center.1, self.height, self.map_size.1 * self.zoom);
+    }
+
+    fn map_to_screen(&self, (x, y): (f64, f64)) -> Point {
+        Point {
+            x: (x - self.center.0) * self.zoom * self.map_size.0 + self.width / 2.0,
+            y: (y - self.center.1) * self.zoom * self.map_size.1 + self.height / 2.0,
+        }
+    }
+
+    fn screen_to_map(&self, point: Point) -> (f64, f64) {
+        (
+            self.center.0 + (point.x - self.width / 2.0) / (self.zoom * self.map_size.0),
+            self.center.1 + (point.y - self.height / 2.0) / (self.zoom * self.map_size.1),
+        )
+    }
+
+    /// Where `coordinate` is in the window, which may be outside of it.
+    /// `None` if the projection can't show it at all.
+    pub fn to_screen(&self, coordinate: Coordinate) -> Option<Point> {
+        self.projection
+            .is_visible(coordinate)
+            .then(|| self.map_to_screen(self.projection.project(coordinate)))
+    }
+
+    /// The coordinate under a point in the window, or `None` off the map.
+    pub fn to_coordinate(&self, point: Point) -> Option<Coordinate> {
+        let (x, y) = self.screen_to_map(point);
+        self.projection.inverse(x, y)
+    }
+
+    /// Moves the view by a distance in pixels, the way the map moves when dragged.
+    pub fn pan(&mut self, dx: f64, dy: f64) {
+        self.center.0 -= dx / (self.map_size.0 * self.zoom);
+        self.center.1 -= dy / (self.map_size.1 * self.zoom);
+        self.clamp_center();
+    }
+
+    /// Zooms by `factor`, keeping the point under `anchor` in place.
+    pub fn zoom_at(&mut self, factor: f64, anchor: Point) {
+        let (x, y) = self.screen_to_map(anchor);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.center = (
+            x - (anchor.x - self.width / 2.0) / (self.zoom * self.map_size.0),
+            y - (anchor.y - self.height / 2.0) / (self.zoom * self.map_size.1),
+        );
+        self.clamp_center();
+    }
+
+    /// Mouse wheel zooms around the mouse pointer, dragging with the left button pans.
+    pub fn handle_event(&mut self, event: &Event) -> ViewportInput {
+        match event {
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => self.press(*x, *y),
+            Event::MouseMotion { x, y, .. } => self.motion(*x, *y),
+            Event::MouseButtonUp { mouse_btn: MouseButton::Left, x, y, .. } => self.release(*x, *y),
+            Event::MouseWheel { y, .. } => self.wheel(*y),
+            _ => ViewportInput::Ignored,
+        }
+    }
+
+    fn press(&mut self, x: i32, y: i32) -> ViewportInput {
+        self.drag = Some(Drag {
+            start: (x, y),
+            last: (x, y),
+            moved: false,
+        });
+        ViewportInput::Ignored
+    }
+
+    fn motion(&mut self, x: i32, y: i32) -> ViewportInput {
+        self.mouse = (x, y);
+        let Some(drag) = &mut self.drag else {
+            return ViewportInput::Ignored;
+        };
+        let distance = ((x - drag.start.0) as f64).hypot((y - drag.start.1) as f64);
+        drag.moved |= distance > DRAG_THRESHOLD;
+        if !drag.moved {
+            return ViewportInput::Ignored;
+        }
+        let (dx, dy) = (x - drag.last.0, y - drag.last.1);
+        drag.last = (x, y);
+        self.pan(dx as f64, dy as f64);
+        ViewportInput::Moved
+    }
+
+    fn release(&mut self, x: i32, y: i32) -> ViewportInput {
+        match self.drag.take() {
+            Some(drag) if drag.moved => ViewportInput::Ignored,
+            _ => ViewportInput::Click(Point { x: x as f64, y: y as f64 }),
+        }
+    }
+
+    fn wheel(&mut self, steps: i32) -> ViewportInput {
+        if steps == 0 {
+            return ViewportInput::Ignored;
+        }
+        let anchor = Point {
+            x: self.mouse.0 as f64,
+            y: self.mouse.1 as f64,
+        };
+        self.zoom_at(ZOOM_STEP.powi(steps), anchor);
+        ViewportInput::Moved
+    }
+
+    /// Draws the countries as seen through the viewport, at full resolution for the current zoom.
+    pub fn render(&self, countries: &[CountryShape], sea_color: [u8; 4], land_color: [u8; 4]) -> SimpleImage {
+        let mut image = SimpleImage::new(self.width as u32, self.height as u32);
+        let outline = self
+            .projection
+            .outline()
+            .into_iter()
+            .map(|x| self.map_to_screen(x))
+            .collect::<Vec<_>>();
+        image.draw_polygon(&outline, sea_color);
+        let screen = |ring: &[Coordinate]| {
+            ring.iter()
+                .map(|x| self.map_to_screen(self.projection.project(*x)))
+                .collect::<Vec<_>>()
+        };
+        fill_countries(&mut image, countries, &screen, land_color, sea_color);
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::projection::{Equirectangular, Orthographic};
+
+    fn at(latitude: f64, longitude: f64) -> Coordinate {
+        Coordinate { latitude, longitude }
+    }
+
+    fn close(a: Point, b: Point) -> bool {
+        (a.x - b.x).abs() < 1e-6 && (a.y - b.y).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_zoom_keeps_anchor() {
+        let mut viewport = Viewport::new(Equirectangular, 1500, 750);
+        let stockholm = at(59.33, 18.07);
+        let anchor = viewport.to_screen(stockholm).unwrap();
+        viewport.zoom_at(8.0, anchor);
+        assert_eq!(viewport.zoom(), 8.0);
+        assert!(close(viewport.to_screen(stockholm).unwrap(), anchor));
+        let back = viewport.to_coordinate(anchor).unwrap();
+        assert!((back.latitude - 59.33).abs() < 1e-9 && (back.longitude - 18.07).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pan_is_clamped() {
+        let mut viewport = Viewport::new(Equirectangular, 1500, 750);
+        viewport.pan(500.0, 0.0);
+        assert!(close(viewport.to_screen(at(90.0, -180.0)).unwrap(), Point { x: 0.0, y: 0.0 }));
+
+        viewport.set_zoom(2.0);
+        viewport.pan(10_000.0, 10_000.0);
+        let corner = viewport.to_coordinate(Point { x: 0.0, y: 0.0 }).unwrap();
+        assert!((corner.latitude - 90.0).abs() < 1e-9 && (corner.longitude + 180.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_click_and_drag() {
+        let mut viewport = Viewport::new(Equirectangular, 1500, 750);
+        viewport.set_zoom(4.0);
+        assert!(matches!(viewport.press(100, 100), ViewportInput::Ignored));
+        assert!(matches!(viewport.motion(102, 101), ViewportInput::Ignored));
+        match viewport.release(102, 101) {
+            ViewportInput::Click(point) => assert!(close(point, Point { x: 102.0, y: 101.0 })),
+            other => panic!("expected a click, got {:?}", other),
+        }
+
+        let before = viewport.to_coordinate(Point { x: 100.0, y: 100.0 }).unwrap();
+        viewport.press(100, 100);
+        assert!(matches!(viewport.motion(150, 100), ViewportInput::Moved));
+        assert!(matches!(viewport.release(150, 100), ViewportInput::Ignored));
+        let after = viewport.to_coordinate(Point { x: 150.0, y: 100.0 }).unwrap();
+        assert!((before.longitude - after.longitude).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_keeps_aspect_ratio() {
+        let mut viewport = Viewport::new(Orthographic::new(at(0.0, 0.0)), 1500, 750);
+        let center = viewport.to_screen(at(0.0, 0.0)).unwrap();
+        assert!(close(center, Point { x: 750.0, y: 375.0 }));
+        let east = viewport.to_screen(at(0.0, 90.0)).unwrap();
+        let north = viewport.to_screen(at(90.0, 0.0)).unwrap();
+        assert!((east.x - 1125.0).abs() < 1e-6 && (north.y - 0.0).abs() < 1e-6);
+        assert!(viewport.to_coordinate(Point { x: 100.0, y: 375.0 }).is_none());
+
+        // Panning sideways does nothing while the globe is narrower than the window
+        viewport.pan(300.0, 0.0);
+        assert!(close(viewport.to_screen(at(0.0, 0.0)).unwrap(), center));
+        viewport.set_zoom(4.0);
+        let east = viewport.to_screen(at(0.0, 10.0)).unwrap();
+        let north = viewport.to_screen(at(10.0, 0.0)).unwrap();
+        assert!(((east.x - 750.0) - (375.0 - north.y)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_wheel_zooms_at_mouse() {
+        let mut viewport = Viewport::new(Equirectangular, 1500, 750);
+        viewport.motion(300, 200);
+        let under_mouse = viewport.to_coordinate(Point { x: 300.0, y: 200.0 }).unwrap();
+        viewport.wheel(3);
+        assert!((viewport.zoom() - ZOOM_STEP.powi(3)).abs() < 1e-9);
+        let point = viewport.to_screen(under_mouse).unwrap();
+        assert!(close(point, Point { x: 300.0, y: 200.0 }));
+        viewport.wheel(-10);
+        assert_eq!(viewport.zoom(), MIN_ZOOM);
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use apricity::Coordinate;
+
+use crate::geo::countries::{self, CountryShape};
+use crate::DatasetError;
+
+/// Finds the country a coordinate lies in, using the country borders in `countries.geojson`.
+///
+/// Each country and each of its polygons has a bounding box, so a lookup only does the
+/// point-in-polygon test for the few polygons whose box contains the coordinate.
+#[derive(Clone, Debug)]
+pub struct ReverseGeocoder {
+    countries: Vec<CountryShape>,
+}
+
+impl ReverseGeocoder {
+    pub fn new(countries: Vec<CountryShape>) -> ReverseGeocoder {
+        ReverseGeocoder { countries }
+    }
+
+    pub fn open_default() -> Result<ReverseGeocoder, DatasetError> {
+        Ok(ReverseGeocoder::new(countries::load_countries()?))
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<ReverseGeocoder, DatasetError> {
+        Ok(ReverseGeocoder::new(countries::read_countries(path)?))
+    }
+
+    pub fn from_geojson(json: &str) -> Result<ReverseGeocoder, DatasetError> {
+        Ok(ReverseGeocoder::new(countries::parse_countries(json)?))
+    }
+
+    pub fn countries(&self) -> &[CountryShape] {
+        &self.countries
+    }
+
+    /// The country `coordinate` lies in, or `None` at sea.
+    pub fn country_at(&self, coordinate: Coordinate) -> Option<&CountryShape> {
+        self.countries.iter().find(|x| x.contains(coordinate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::countries::tests::COUNTRIES;
+
+    fn at(latitude: f64, longitude: f64) -> Coordinate {
+        Coordinate { latitude, longitude }
+    }
+
+    #[test]
+    fn test_country_at() {
+        let geocoder = ReverseGeocoder::from_geojson(COUNTRIES).unwrap();
+        let name = |latitude, longitude| geocoder.country_at(at(latitude, longitude)).map(|x| x.name.as_str());
+        assert_eq!(name(2.0, 2.0), Some("Squareland"));
+        assert_eq!(name(5.0, 5.0), None);
+        assert_eq!(name(-4.5, 21.0), Some("Islands"));
+        assert_eq!(name(41.0, 31.0), Some("Islands"));
+        assert_eq!(name(-3.5, 20.1), None);
+        assert_eq!(name(-50.0, 100.0), None);
+    }
+}
@@ -0,0 +1,260 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
+use apricity::Coordinate;
+
+use crate::names::comma_separated;
+use crate::{City, CityData, CityGeometry, DatasetError, Timestamp};
+
+/// `datasetid` given to cities that were read from a GeoNames dump.
+pub const GEONAMES_DATASET_ID: &str = "geonames-tsv";
+
+// Columns of the GeoNames "geoname" table, as documented in the dump's readme.txt.
+const GEONAME_ID: usize = 0;
+const NAME: usize = 1;
+const ASCII_NAME: usize = 2;
+const ALTERNATE_NAMES: usize = 3;
+const LATITUDE: usize = 4;
+const LONGITUDE: usize = 5;
+const FEATURE_CLASS: usize = 6;
+const FEATURE_CODE: usize = 7;
+const COUNTRY_CODE: usize = 8;
+const ADMIN1_CODE: usize = 10;
+const ADMIN2_CODE: usize = 11;
+const ADMIN3_CODE: usize = 12;
+const ADMIN4_CODE: usize = 13;
+const POPULATION: usize = 14;
+const DEM: usize = 16;
+const TIMEZONE: usize = 17;
+const MODIFICATION_DATE: usize = 18;
+const COLUMN_COUNT: usize = 19;
+
+/// Whether `path` looks like a GeoNames dump (`cities15000.txt`, `allCountries.txt`, ...)
+/// rather than an OpenDataSoft JSON export.
+pub fn is_geonames_path(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|x| x.to_str()),
+        Some("txt" | "tsv")
+    )
+}
+
+/// Reads a tab-separated GeoNames dump one line at a time.
+pub struct GeoNamesReader<R> {
+    reader: R,
+    line: usize,
+    buffer: String,
+}
+
+impl GeoNamesReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, DatasetError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|source| DatasetError::Io {
+            path: Some(path.to_path_buf()),
+            source,
+        })?;
+        Ok(GeoNamesReader::new(BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> GeoNamesReader<R> {
+    pub fn new(reader: R) -> Self {
+        GeoNamesReader {
+            reader,
+            line: 0,
+            buffer: String::new(),
+        }
+    }
+
+    /// Iterates over the cities of the dump, stopping at the first unreadable line.
+    pub fn records(mut self) -> impl Iterator<Item = Result<City, DatasetError>> {
+        let mut done = false;
+        std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let record = self.next_record().transpose();
+            if let None | Some(Err(_)) = record {
+                done = true;
+            }
+            record
+        })
+    }
+
+    /// Like `records`, but yields only the `fields` of each city.
+    pub fn city_data(self) -> impl Iterator<Item = Result<CityData, DatasetError>> {
+        self.records().map(|record| record.map(City::into_city_data))
+    }
+
+    fn next_record(&mut self) -> Result<Option<City>, DatasetError> {
+        loop {
+            self.buffer.clear();
+            let count = self
+                .reader
+                .read_line(&mut self.buffer)
+                .map_err(|source| DatasetError::Io { path: None, source })?;
+            if count == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+
+            let line = self.buffer.trim_end_matches(['\r', '\n']);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            return parse_line(line, self.line).map(Some);
+        }
+    }
+}
+
+fn parse_line(line: &str, line_number: usize) -> Result<City, DatasetError> {
+    let columns = line.split('\t').collect::<Vec<_>>();
+    if columns.len() < COLUMN_COUNT {
+        return Err(DatasetError::Column {
+            line: line_number,
+            column: "line",
+            value: format!("{} columns, expected {}", columns.len(), COLUMN_COUNT),
+        });
+    }
+
+    let text = |index: usize| columns[index].to_string();
+    let optional = |index: usize| match columns[index] {
+        "" => None,
+        x => Some(x.to_string()),
+    };
+    // Population and dem are sometimes left empty, which GeoNames means as zero.
+    let integer = |index: usize, column: &'static str| -> Result<i64, DatasetError> {
+        match columns[index] {
+            "" => Ok(0),
+            _ => parse_column(&columns, index, column, line_number),
+        }
+    };
+
+    let coordinates = Coordinate {
+        latitude: parse_column(&columns, LATITUDE, "latitude", line_number)?,
+        longitude: parse_column(&columns, LONGITUDE, "longitude", line_number)?,
+    };
+    let fields = CityData {
+        coordinates,
+        cou_name_en: None,
+        label_en: None,
+        feature_code: optional_column(&columns, FEATURE_CODE, "feature code", line_number)?,
+        population: integer(POPULATION, "population")?,
+        dem: integer(DEM, "dem")?,
+        geoname_id: parse_column(&columns, GEONAME_ID, "geonameid", line_number)?,
+        name: text(NAME),
+        ascii_name: optional(ASCII_NAME),
+        alternate_names: comma_separated::split(columns[ALTERNATE_NAMES]),
+        admin1_code: optional(ADMIN1_CODE),
+        admin2_code: optional(ADMIN2_CODE),
+        admin3_code: optional(ADMIN3_CODE),
+        admin4_code: optional(ADMIN4_CODE),
+        feature_class: optional_column(&columns, FEATURE_CLASS, "feature class", line_number)?,
+        country_code: optional_column(&columns, COUNTRY_CODE, "country code", line_number)?,
+        timezone: text(TIMEZONE),
+        modification_date: parse_column(&columns, MODIFICATION_DATE, "modification date", line_number)?,
+    };
+
+    Ok(City {
+        datasetid: GEONAMES_DATASET_ID.to_string(),
+        recordid: fields.geoname_id.to_string(),
+        record_timestamp: Timestamp::start_of_day(fields.modification_date),
+        geometry: CityGeometry { coordinates },
+        fields,
+    })
+}
+
+fn parse_column<T: FromStr>(
+    columns: &[&str],
+    index: usize,
+    column: &'static str,
+    line_number: usize,
+) -> Result<T, DatasetError> {
+    columns[index].parse().map_err(|_| DatasetError::Column {
+        line: line_number,
+        column,
+        value: columns[index].to_string(),
+    })
+}
+
+/// Like `parse_column`, but a blank column is `None` rather than an error.
+fn optional_column<T: FromStr>(
+    columns: &[&str],
+    index: usize,
+    column: &'static str,
+    line_number: usize,
+) -> Result<Option<T>, DatasetError> {
+    match columns[index] {
+        "" => Ok(None),
+        _ => parse_column(columns, index, column, line_number).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FeatureCode, GeonameId};
+
+    const GOTTINGEN: &str = "2918632\tGöttingen\tGoettingen\tGoettingen,Gottingen,Гёттинген\t51.53443\t9.93228\tP\tPPLA3\tDE\t\t06\t00\t03159\t03159016\t122149\t\t153\tEurope/Berlin\t2019-09-05";
+
+    #[test]
+    fn test_parse_line() {
+        let data = format!("# comment\n\n{}\n", GOTTINGEN);
+        let cities = GeoNamesReader::new(data.as_bytes())
+            .city_data()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(cities.len(), 1);
+        let city = &cities[0];
+        assert_eq!(city.geoname_id, GeonameId(2918632));
+        assert_eq!(city.name, "Göttingen");
+        assert_eq!(city.ascii_name.as_deref(), Some("Goettingen"));
+        assert_eq!(city.alternate_names, ["Goettingen", "Gottingen", "Гёттинген"]);
+        assert_eq!(city.coordinates.latitude, 51.53443);
+        assert_eq!(city.coordinates.longitude, 9.93228);
+        assert_eq!(city.feature_code, Some(FeatureCode::Ppla3));
+        assert_eq!(city.country_code.unwrap().as_str(), "DE");
+        assert_eq!(city.admin1_code.as_deref(), Some("06"));
+        assert_eq!(city.population, 122149);
+        assert_eq!(city.dem, 153);
+        assert_eq!(city.timezone, "Europe/Berlin");
+    }
+
+    #[test]
+    fn test_blank_country_and_feature() {
+        // An undersea feature without a country, and a place without a feature class or code
+        let data = "3373405\tAtlantic Ocean\tAtlantic Ocean\t\t10.0\t-25.0\tH\tOCN\t\t\t\t\t\t\t0\t\t-5000\t\t2012-01-01\n\
+                    6295630\tEarth\tEarth\t\t0.0\t0.0\t\t\t\t\t\t\t\t\t6814400000\t\t-9999\t\t2018-01-01\n";
+        let cities = GeoNamesReader::new(data.as_bytes())
+            .city_data()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(cities.len(), 2);
+        assert_eq!(cities[0].country_code, None);
+        assert_eq!(cities[0].feature_code.as_ref().map(|x| x.as_str()), Some("OCN"));
+        assert_eq!(cities[1].feature_class, None);
+        assert_eq!(cities[1].feature_code, None);
+
+        // And they come back the same way from JSON
+        let json = serde_json::to_string(&cities[0]).unwrap();
+        let city = serde_json::from_str::<CityData>(&json).unwrap();
+        assert_eq!(city.country_code, None);
+    }
+
+    #[test]
+    fn test_bad_column() {
+        let data = format!("{}\n{}\n", GOTTINGEN, GOTTINGEN.replace("51.53443", "north"));
+        let mut records = GeoNamesReader::new(data.as_bytes()).records();
+        assert!(records.next().unwrap().is_ok());
+        match records.next() {
+            Some(Err(DatasetError::Column { line, column, value })) => {
+                assert_eq!(line, 2);
+                assert_eq!(column, "latitude");
+                assert_eq!(value, "north");
+            }
+            _ => panic!("Expected a column error"),
+        }
+        assert!(records.next().is_none());
+    }
+}
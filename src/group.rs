@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use crate::{CityData, CountryCode, FeatureCode};
+
+// Keys to group by. Any `Fn(&CityData) -> K` works as well.
+
+/// Places outside any country are grouped under `None`.
+pub fn country(city: &CityData) -> Option<CountryCode> {
+    city.country_code
+}
+
+/// Admin1 codes are only unique within a country, so the key includes both.
+pub fn admin1(city: &CityData) -> (Option<CountryCode>, Option<String>) {
+    (city.country_code, city.admin1_code.clone())
+}
+
+pub fn timezone(city: &CityData) -> String {
+    city.timezone.clone()
+}
+
+pub fn feature_code(city: &CityData) -> Option<FeatureCode> {
+    city.feature_code.clone()
+}
+
+/// Cities split into groups by a key, ordered by key.
+#[derive(Clone, Debug)]
+pub struct Groups<'a, K> {
+    groups: BTreeMap<K, Vec<&'a CityData>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PopulationSummary {
+    pub count: usize,
+    pub total: i64,
+    pub mean: f64,
+    pub median: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ElevationRange {
+    pub min: i64,
+    pub max: i64,
+}
+
+impl<'a, K: Ord> Groups<'a, K> {
+    pub fn new(cities: impl IntoIterator<Item = &'a CityData>, key: impl Fn(&CityData) -> K) -> Self {
+        let mut groups = BTreeMap::<K, Vec<&'a CityData>>::new();
+        for city in cities {
+            groups.entry(key(city)).or_default().push(city);
+        }
+        Groups { groups }
+    }
+
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&[&'a CityData]> {
+        self.groups.get(key).map(|x| x.as_slice())
+    }
+
+    /// Every group and its cities. A group always has at least one city.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &[&'a CityData])> {
+        self.groups.iter().map(|(key, cities)| (key, cities.as_slice()))
+    }
+
+    /// Applies `aggregate` to the cities of every group.
+    pub fn aggregate<T>(&self, aggregate: impl Fn(&[&'a CityData]) -> T) -> Vec<(&K, T)> {
+        self.iter().map(|(key, cities)| (key, aggregate(cities))).collect()
+    }
+
+    pub fn count(&self) -> Vec<(&K, usize)> {
+        self.aggregate(|cities| cities.len())
+    }
+
+    /// The city with the largest population in each group, the first one on a tie.
+    pub fn largest(&self) -> Vec<(&K, &'a CityData)> {
+        self.aggregate(|cities| *cities.iter().min_by_key(|x| std::cmp::Reverse(x.population)).unwrap())
+    }
+
+    /// The `n` cities with the largest population in each group, largest first.
+    pub fn top(&self, n: usize) -> Vec<(&K, Vec<&'a CityData>)> {
+        self.aggregate(|cities| top_by_population(cities, n))
+    }
+
+    pub fn population(&self) -> Vec<(&K, PopulationSummary)> {
+        self.aggregate(population_summary)
+    }
+
+    pub fn elevation(&self) -> Vec<(&K, ElevationRange)> {
+        self.aggregate(|cities| elevation_range(cities).unwrap())
+    }
+}
+
+pub fn top_by_population<'a>(cities: &[&'a CityData], n: usize) -> Vec<&'a CityData> {
+    let mut result = cities.to_vec();
+    result.sort_by_key(|x| std::cmp::Reverse(x.population));
+    result.truncate(n);
+    result
+}
+
+pub fn population_summary(cities: &[&CityData]) -> PopulationSummary {
+    let count = cities.len();
+    let total = cities.iter().map(|x| x.population).sum::<i64>();
+    let mut populations = cities.iter().map(|x| x.population).collect::<Vec<_>>();
+    populations.sort_unstable();
+    let median = match count {
+        0 => 0.0,
+        _ if count % 2 == 1 => populations[count / 2] as f64,
+        _ => (populations[count / 2 - 1] + populations[count / 2]) as f64 / 2.0,
+    };
+    PopulationSummary {
+        count,
+        total,
+        mean: if count == 0 { 0.0 } else { total as f64 / count as f64 },
+        median,
+    }
+}
+
+/// Lowest and highest `dem`, or `None` if there are no cities.
+pub fn elevation_range(cities: &[&CityData]) -> Option<ElevationRange> {
+    let min = cities.iter().map(|x| x.dem).min()?;
+    let max = cities.iter().map(|x| x.dem).max()?;
+    Some(ElevationRange { min, max })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn city(name: &str, country_code: &str, population: i64, dem: i64) -> CityData {
+        test_support::city(name).country(country_code).population(population).dem(dem).build()
+    }
+
+    fn cities() -> Vec<CityData> {
+        vec![
+            city("Uppsala", "SE", 133_117, 21),
+            city("Stockholm", "SE", 1_515_017, 28),
+            city("Göteborg", "SE", 572_799, 12),
+            city("Oslo", "NO", 580_000, 23),
+        ]
+    }
+
+    #[test]
+    fn test_largest_and_count() {
+        let cities = cities();
+        let groups = Groups::new(&cities, country);
+        let largest = groups.largest();
+        assert_eq!(largest.len(), 2);
+        assert_eq!(largest[0].0.unwrap().as_str(), "NO");
+        assert_eq!(largest[1].1.name, "Stockholm");
+        assert_eq!(groups.count()[1].1, 3);
+    }
+
+    #[test]
+    fn test_largest_keeps_first_on_tie() {
+        let cities = vec![city("Bergen", "NO", 580_000, 12), city("Oslo", "NO", 580_000, 23)];
+        let groups = Groups::new(&cities, country);
+        assert_eq!(groups.largest()[0].1.name, "Bergen");
+    }
+
+    #[test]
+    fn test_summaries() {
+        let cities = cities();
+        let groups = Groups::new(&cities, country);
+        let sweden = Some("SE".parse().unwrap());
+        let summary = population_summary(groups.get(&sweden).unwrap());
+        assert_eq!(summary.total, 2_220_933);
+        assert_eq!(summary.median, 572_799.0);
+        assert_eq!(groups.elevation()[1].1, ElevationRange { min: 12, max: 28 });
+        let top = groups.top(2);
+        let names = top[1].1.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["Stockholm", "Göteborg"]);
+    }
+
+    #[test]
+    fn test_closure_key() {
+        let cities = cities();
+        let groups = Groups::new(&cities, |x| x.population >= 500_000);
+        assert_eq!(groups.count(), [(&false, 1), (&true, 3)]);
+    }
+}
@@ -1,93 +1,19 @@
-use std::fs;
-use std::error::*;
-use serde::*;
-use apricity::{self, gui::*, Coordinate, Point};
+use std::error::Error;
 
-const COUNTRY_DATA_FILE_PATH: &str = "countries.geojson";
+use apricity::gui::SimpleImage;
 
-#[derive(Debug,Clone,Deserialize)]
-#[serde(tag = "type")]
-struct FeatureCollection {
-    features : Vec<Feature>,
-}
-
-#[derive(Debug,Clone,Deserialize)]
-#[serde(tag = "type")]
-struct Feature {
-    properties : Property,
-    geometry : MultiPolygon,
-}
-
-#[derive(Debug,Clone,Deserialize)]
-#[serde(tag = "type")]
-struct Property {
-    #[serde(rename = "ADMIN")]
-    admin: String,
-    // #[serde(rename = "ISO_A2")]
-    // iso_a2: String,
-    // #[serde(rename = "ISO_A3")]
-    // iso_a3: String,
-}
-
-#[derive(Debug,Clone,Deserialize)]
-#[serde(tag = "type")]
-struct MultiPolygon {
-    coordinates : Vec<Vec<Vec<Coordinate>>>
-}
+use crate::geo::map::create_projected_world_map;
+use crate::geo::projection::{Equirectangular, Projection};
+pub use crate::geo::map::{draw_image, Alignment};
 
-pub enum Alignment {
-    Left,
-    Center,
-    Right
-}
+/// The game uses a darker, slightly transparent map so the text on top stays readable.
+pub const GAME_SEA_COLOR: [u8; 4] = [0xFF, 0x00, 0x00, 0xC0];
+pub const GAME_LAND_COLOR: [u8; 4] = [0x00, 0xA8, 0x00, 0xFF];
 
 pub fn create_world_map(width: u32, height: u32) -> Result<SimpleImage, Box<dyn Error>> {
-    let json_data = fs::read_to_string(COUNTRY_DATA_FILE_PATH)?;
-    let all_country_data = serde_json::from_str::<FeatureCollection>(&json_data)?;
-
-    let width_f = width as f64;
-    let height_f = height as f64;
-
-    let mut world_map = SimpleImage::new(width, height);
-
-    // Draw background
-    println!("Drawing background");
-    world_map.draw_polygon(&[
-        Point {x: 0.0, y: 0.0},
-        Point {x: 0.0, y: height_f - 1.0},
-        Point {x: width_f - 1.0, y: height_f - 1.0},
-        Point {x: width_f - 1.0, y: 0.0}
-    ], [0xFF, 0x00, 0x00, 0xC0]);
-
-    for country_data in all_country_data.features.iter() {
-        println!("Drawing {}", country_data.properties.admin);
-
-        for multi_polygon_data in country_data.geometry.coordinates.iter() {
-            for polygon_data in multi_polygon_data {
-                let vertices = polygon_data.into_iter()
-                    .map(|vertex| vertex.screen(width_f, height_f))
-                    .collect::<Vec<_>>();
-                world_map.draw_polygon(&vertices, [0x00, 0xA8, 0x00, 0xFF]);
-            }
-        }
-    }
-
-    Ok(world_map)
+    create_game_map(width, height, &Equirectangular)
 }
 
-pub fn draw_image(window: &mut SimpleWindow, image: &SimpleImage, position: (i32, i32), alignment: Alignment) {
-    let image_width = image.width();
-    let image_height = image.height();
-    let mut x = position.0;
-    let mut y = position.1;
-    match alignment {
-        Alignment::Center => x = position.0 - (image_width/2) as i32,
-        Alignment::Right => y = position.0 - image_width as i32,
-        Alignment::Left => {}
-    }
-    let draw_rect = Rect::new(x, y, image_width, image_height);
-    let blend = image_width < window.width() || image_height < window.height();
-    if let Err(error) = window.draw_image(image, Some(draw_rect), blend) {
-        println!("Couldn't draw image: {}", error);
-    }
-}
\ No newline at end of file
+pub fn create_game_map(width: u32, height: u32, projection: &dyn Projection) -> Result<SimpleImage, Box<dyn Error>> {
+    create_projected_world_map(width, height, projection, GAME_SEA_COLOR, GAME_LAND_COLOR)
+}
@@ -7,63 +7,102 @@ pub use apricity::*;
 // After Exercise 8:
 pub mod protocol;
 
-use std::fs;
+pub mod dataset;
+pub mod cache;
+pub mod geonames;
+pub mod names;
+pub mod types;
+pub mod query;
+pub mod query_parser;
+pub mod group;
+pub mod output;
+pub mod spatial;
+pub mod geocode;
+pub mod geo;
+pub mod score;
+pub mod room;
+pub mod selection;
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[cfg(test)]
+mod test_support;
+
+pub use dataset::{CityDataset, CityReader, DatasetError};
+pub use geo::CountryShape;
+pub use geocode::ReverseGeocoder;
+pub use geonames::GeoNamesReader;
+pub use names::NameIndex;
+pub use query::{CityFilter, CityQuery};
+pub use room::Room;
+pub use score::Leaderboard;
+pub use selection::{CitySelector, SeededRng, SelectionPolicy};
+pub use spatial::{BoundingBox, SpatialIndex};
+pub use types::{CountryCode, Date, FeatureClass, FeatureCode, GeonameId, Timestamp};
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct City {
     pub datasetid: String,
     pub recordid: String,
     pub fields: CityData,
-    pub record_timestamp: String,
+    pub record_timestamp: Timestamp,
     pub geometry: CityGeometry,
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct CityGeometry {
     pub coordinates: Coordinate,
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct CityData {
     pub coordinates: Coordinate,
     pub cou_name_en: Option<String>,
     pub label_en: Option<String>,
-    pub feature_code: String,
+    /// Blank for a few places in the GeoNames dumps, like the feature class and country code.
+    #[serde(default, with = "types::blank_as_none")]
+    pub feature_code: Option<FeatureCode>,
     pub population: i64,
     pub dem: i64,
-    pub geoname_id: String,
+    pub geoname_id: GeonameId,
     pub name: String,
+    pub ascii_name: Option<String>,
+    #[serde(default, with = "names::comma_separated")]
+    pub alternate_names: Vec<String>,
     pub admin1_code: Option<String>,
     pub admin2_code: Option<String>,
     pub admin3_code: Option<String>,
     pub admin4_code: Option<String>,
-    pub feature_class: String,
-    pub country_code: String,
+    #[serde(default, with = "types::blank_as_none")]
+    pub feature_class: Option<FeatureClass>,
+    /// `None` for places outside any country, e.g. in the oceans.
+    #[serde(default, with = "types::blank_as_none")]
+    pub country_code: Option<CountryCode>,
     pub timezone: String,
-    pub modification_date: String,
+    pub modification_date: Date,
+}
+
+impl City {
+    /// The `fields` of the city, with `coordinates` taken from `geometry`.
+    pub fn into_city_data(mut self) -> CityData {
+        self.fields.coordinates = self.geometry.coordinates;
+        self.fields
+    }
 }
 
 impl CityData {
     pub fn country_name_eng(&self) -> &str {
         match &self.cou_name_en {
             Some(x) => x,
-            None => &self.country_code,
+            None => self.country_code.as_ref().map_or("", |x| x.as_str()),
         }
     }
 }
 
-pub fn load_cities() -> Result<Vec<City>, Box<dyn std::error::Error>> {
-    let file_name = "cities100k.json";
-    let json_data = fs::read_to_string(file_name)?;
-    let cities = serde_json::from_str::<Vec<City>>(&json_data)?;
-
-    Ok(cities)
+/// Loads every city from `dataset::default_cities_path()`, through the binary cache.
+pub fn load_cities() -> Result<Vec<City>, DatasetError> {
+    Ok(CityDataset::open_default()?.into_cities())
 }
 
-pub fn load_city_data() -> Result<Vec<CityData>, Box<dyn std::error::Error>> {
-    let file_name = "cities100k.json";
-    let json_data = fs::read_to_string(file_name)?;
-    let city_data = serde_json::from_str::<Vec<City>>(&json_data)?;
-
-    Ok(city_data.into_iter().map(|mut x| { x.fields.coordinates = x.geometry.coordinates; x.fields }).collect())
-}
\ No newline at end of file
+/// Loads the `fields` of every city from `dataset::default_cities_path()`, through the binary cache.
+pub fn load_city_data() -> Result<Vec<CityData>, DatasetError> {
+    Ok(CityDataset::open_default()?.into_city_data())
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use crate::{CityData, GeonameId};
+
+/// Folds a city name into the form used as a key in `NameIndex`:
+/// lowercase, without accents, with runs of spaces, dashes and apostrophes collapsed into one space.
+/// Accents are dropped whether they are precomposed or written as combining marks (NFD).
+///
+/// "Göttingen", "GOTTINGEN" and " gottingen " all fold to "gottingen".
+pub fn normalize_name(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut pending_space = false;
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_whitespace() || matches!(c, '-' | '\'' | '’' | '`' | '.' | ',') {
+            pending_space = !result.is_empty();
+            continue;
+        }
+        if ('\u{300}'..='\u{36f}').contains(&c) {
+            continue;
+        }
+        if pending_space {
+            result.push(' ');
+            pending_space = false;
+        }
+        match fold_accent(c) {
+            Some(folded) => result.push_str(folded),
+            None => result.push(c),
+        }
+    }
+    result
+}
+
+/// Latin letters with diacritics, mapped to their plain ASCII spelling.
+fn fold_accent(c: char) -> Option<&'static str> {
+    let folded = match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'ď' | 'đ' | 'ð' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'ĥ' | 'ħ' => "h",
+        'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'ĵ' => "j",
+        'ķ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'œ' => "oe",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'ś' | 'ŝ' | 'ş' | 'š' | 'ș' => "s",
+        'ß' => "ss",
+        'ţ' | 'ť' | 'ŧ' | 'ț' => "t",
+        'þ' => "th",
+        'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'ŵ' => "w",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    };
+    Some(folded)
+}
+
+/// Every name a city is known by: `name`, `ascii_name` and the `alternate_names`.
+pub fn names_of(city: &CityData) -> impl Iterator<Item = &String> {
+    std::iter::once(&city.name)
+        .chain(city.ascii_name.iter())
+        .chain(city.alternate_names.iter())
+}
+
+/// Finds cities by any of their names: `name`, `ascii_name` or one of the `alternate_names`.
+#[derive(Clone, Debug, Default)]
+pub struct NameIndex {
+    geoname_ids: HashMap<String, Vec<GeonameId>>,
+}
+
+impl NameIndex {
+    pub fn new<'a>(cities: impl IntoIterator<Item = &'a CityData>) -> NameIndex {
+        let mut index = NameIndex::default();
+        for city in cities {
+            index.insert(city);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, city: &CityData) {
+        for name in names_of(city) {
+            let key = normalize_name(name);
+            if key.is_empty() {
+                continue;
+            }
+            let ids = self.geoname_ids.entry(key).or_default();
+            if !ids.contains(&city.geoname_id) {
+                ids.push(city.geoname_id);
+            }
+        }
+    }
+
+    /// The `geoname_id` of every city known by `name`. Several cities can share a name.
+    pub fn lookup(&self, name: &str) -> &[GeonameId] {
+        match self.geoname_ids.get(&normalize_name(name)) {
+            Some(ids) => ids,
+            None => &[],
+        }
+    }
+
+    /// Whether `name` is one of the names of the city with `geoname_id`,
+    /// e.g. to accept a typed answer in the guessing game.
+    pub fn is_name_of(&self, name: &str, geoname_id: GeonameId) -> bool {
+        self.lookup(name).contains(&geoname_id)
+    }
+}
+
+/// Serde helper for `CityData::alternate_names`: a comma-separated string in the JSON export,
+/// a plain list in binary formats such as the cache.
+pub(crate) mod comma_separated {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(names: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            names.join(",").serialize(serializer)
+        } else {
+            names.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+        if deserializer.is_human_readable() {
+            let names = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+            Ok(split(&names))
+        } else {
+            Vec::<String>::deserialize(deserializer)
+        }
+    }
+
+    pub fn split(names: &str) -> Vec<String> {
+        names
+            .split(',')
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gottingen() -> CityData {
+        serde_json::from_str(r#"{
+            "coordinates": [51.53443, 9.93228],
+            "feature_code": "PPLA3",
+            "population": 122149,
+            "dem": 153,
+            "geoname_id": "2918632",
+            "name": "Göttingen",
+            "ascii_name": "Goettingen",
+            "alternate_names": "Choettingen,Getingen,Goettingen,Gottinga,Гёттинген,哥廷根",
+            "feature_class": "P",
+            "country_code": "DE",
+            "timezone": "Europe/Berlin",
+            "modification_date": "2019-09-05"
+        }"#).unwrap()
+    }
+
+    #[test]
+    fn test_alternate_names_are_split() {
+        let city = gottingen();
+        assert_eq!(city.ascii_name.as_deref(), Some("Goettingen"));
+        assert_eq!(city.alternate_names.len(), 6);
+        assert_eq!(city.alternate_names[4], "Гёттинген");
+    }
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("Göttingen"), "gottingen");
+        assert_eq!(normalize_name("  SAN   josé "), "san jose");
+        assert_eq!(normalize_name("Winston-Salem"), "winston salem");
+        assert_eq!(normalize_name("Гёттинген"), "гёттинген");
+        assert_eq!(normalize_name("Go\u{308}ttingen"), "gottingen");
+        assert_eq!(normalize_name("San Jose\u{301}"), "san jose");
+    }
+
+    #[test]
+    fn test_lookup() {
+        let city = gottingen();
+        let index = NameIndex::new([&city]);
+        for name in ["Goettingen", "Gottingen", "göttingen", "Гёттинген", "哥廷根"] {
+            assert_eq!(index.lookup(name), [GeonameId(2918632)], "{}", name);
+        }
+        assert!(index.is_name_of("gottinga", GeonameId(2918632)));
+        assert!(index.lookup("Berlin").is_empty());
+    }
+}
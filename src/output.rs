@@ -0,0 +1,263 @@
+use std::fmt::{Display, Formatter};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde_json::json;
+use unicode_width::UnicodeWidthStr;
+
+use crate::CityData;
+
+/// How `write_cities` renders a list of cities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Aligned columns for reading in a terminal.
+    Table,
+    /// Comma-separated values with a header row, quoted as in RFC 4180.
+    Csv,
+    /// A pretty-printed JSON array of `CityData`.
+    Json,
+    /// One `CityData` JSON object per line.
+    JsonLines,
+    /// A GeoJSON FeatureCollection with a Point feature per city.
+    GeoJson,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 5] = [
+        OutputFormat::Table,
+        OutputFormat::Csv,
+        OutputFormat::Json,
+        OutputFormat::JsonLines,
+        OutputFormat::GeoJson,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Table => "table",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+            OutputFormat::JsonLines => "jsonl",
+            OutputFormat::GeoJson => "geojson",
+        }
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OutputFormat::ALL
+            .into_iter()
+            .find(|x| x.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown format {:?}", s))
+    }
+}
+
+/// The columns written by the table and CSV formats.
+const COLUMNS: [&str; 10] = [
+    "geoname_id",
+    "name",
+    "country_code",
+    "admin1_code",
+    "feature_code",
+    "population",
+    "dem",
+    "latitude",
+    "longitude",
+    "timezone",
+];
+
+fn columns(city: &CityData) -> [String; 10] {
+    [
+        city.geoname_id.to_string(),
+        city.name.clone(),
+        city.country_code.map(|x| x.to_string()).unwrap_or_default(),
+        city.admin1_code.clone().unwrap_or_default(),
+        city.feature_code.as_ref().map(|x| x.to_string()).unwrap_or_default(),
+        city.population.to_string(),
+        city.dem.to_string(),
+        format!("{:.5}", city.coordinates.latitude),
+        format!("{:.5}", city.coordinates.longitude),
+        city.timezone.clone(),
+    ]
+}
+
+fn is_numeric_column(index: usize) -> bool {
+    matches!(COLUMNS[index], "geoname_id" | "population" | "dem" | "latitude" | "longitude")
+}
+
+pub fn write_cities(out: &mut dyn Write, cities: &[&CityData], format: OutputFormat) -> io::Result<()> {
+    match format {
+        OutputFormat::Table => write_table(out, cities),
+        OutputFormat::Csv => write_csv(out, cities),
+        OutputFormat::Json => write_json(out, cities),
+        OutputFormat::JsonLines => write_json_lines(out, cities),
+        OutputFormat::GeoJson => write_geojson(out, cities),
+    }
+}
+
+/// Columns are padded by display width, so names with accents or wide characters like
+/// "Göttingen" or "哥廷根" line up. Numbers are right-aligned.
+pub fn write_table(out: &mut dyn Write, cities: &[&CityData]) -> io::Result<()> {
+    let rows = cities.iter().map(|x| columns(x)).collect::<Vec<_>>();
+    let mut widths = COLUMNS.map(|x| x.width());
+    for row in &rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.width());
+        }
+    }
+
+    let header = COLUMNS.map(|x| x.to_string());
+    for row in std::iter::once(&header).chain(&rows) {
+        let mut line = String::new();
+        for (index, value) in row.iter().enumerate() {
+            let padding = " ".repeat(widths[index] - value.width());
+            if index > 0 {
+                line.push_str("  ");
+            }
+            if is_numeric_column(index) {
+                line.push_str(&padding);
+                line.push_str(value);
+            } else {
+                line.push_str(value);
+                line.push_str(&padding);
+            }
+        }
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+pub fn write_csv(out: &mut dyn Write, cities: &[&CityData]) -> io::Result<()> {
+    fn write_row(out: &mut dyn Write, values: &[String]) -> io::Result<()> {
+        let values = values.iter().map(|x| csv_field(x)).collect::<Vec<_>>();
+        write!(out, "{}\r\n", values.join(","))
+    }
+    write_row(out, &COLUMNS.map(|x| x.to_string()))?;
+    for city in cities {
+        write_row(out, &columns(city))?;
+    }
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn write_json(out: &mut dyn Write, cities: &[&CityData]) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, cities)?;
+    writeln!(out)
+}
+
+pub fn write_json_lines(out: &mut dyn Write, cities: &[&CityData]) -> io::Result<()> {
+    for city in cities {
+        serde_json::to_writer(&mut *out, city)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// The city fields become the feature properties, except `coordinates` which becomes the
+/// geometry. GeoJSON positions are longitude first.
+pub fn write_geojson(out: &mut dyn Write, cities: &[&CityData]) -> io::Result<()> {
+    let mut features = Vec::with_capacity(cities.len());
+    for city in cities {
+        let mut properties = serde_json::to_value(city)?;
+        if let Some(properties) = properties.as_object_mut() {
+            properties.remove("coordinates");
+        }
+        features.push(json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Point",
+                "coordinates": [city.coordinates.longitude, city.coordinates.latitude],
+            },
+            "properties": properties,
+        }));
+    }
+    let collection = json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+    serde_json::to_writer(&mut *out, &collection)?;
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn city(name: &str, admin1_code: &str) -> CityData {
+        test_support::city(name)
+            .coordinates(51.53443, 9.93228)
+            .feature_code("PPLA3")
+            .population(122149)
+            .dem(153)
+            .geoname_id(2918632)
+            .admin1(admin1_code)
+            .country("DE")
+            .timezone("Europe/Berlin")
+            .modification_date("2019-09-05")
+            .build()
+    }
+
+    fn render(cities: &[&CityData], format: OutputFormat) -> String {
+        let mut out = Vec::new();
+        write_cities(&mut out, cities, format).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_table_aligns_wide_names() {
+        let (a, b) = (city("Göttingen", "06"), city("哥廷根", "06"));
+        let table = render(&[&a, &b], OutputFormat::Table);
+        let columns = table
+            .lines()
+            .map(|line| line.find("DE").map(|x| line[..x].width()))
+            .collect::<Vec<_>>();
+        assert_eq!(columns[1], columns[2]);
+    }
+
+    #[test]
+    fn test_csv_quoting() {
+        let city = city("Halle, \"Saale\"", "14");
+        let csv = render(&[&city], OutputFormat::Csv);
+        let row = csv.split("\r\n").nth(1).unwrap();
+        assert!(row.starts_with("2918632,\"Halle, \"\"Saale\"\"\",DE,14,PPLA3,122149,153,"));
+    }
+
+    #[test]
+    fn test_geojson_point() {
+        let city = city("Göttingen", "06");
+        let geojson = render(&[&city], OutputFormat::GeoJson);
+        let value: serde_json::Value = serde_json::from_str(&geojson).unwrap();
+        let feature = &value["features"][0];
+        assert_eq!(value["type"], "FeatureCollection");
+        let coordinates = city.coordinates;
+        assert_eq!(feature["geometry"]["coordinates"], json!([coordinates.longitude, coordinates.latitude]));
+        assert_eq!(feature["properties"]["name"], "Göttingen");
+        assert!(feature["properties"].get("coordinates").is_none());
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let (a, b) = (city("Göttingen", "06"), city("Kassel", "05"));
+        let lines = render(&[&a, &b], OutputFormat::JsonLines);
+        let names = lines
+            .lines()
+            .map(|x| serde_json::from_str::<CityData>(x).unwrap().name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Göttingen", "Kassel"]);
+    }
+}
@@ -2,13 +2,71 @@
 pub enum ServerMessage {
     Welcome {
         server_name: String,
+        /// What the server picks cities with. Two servers with the same seed, cities and
+        /// selection policy play the same cities in the same order.
+        seed: u64,
     },
+    /// `round` counts up from 1 in each room, and goes with the guesses for `city_name`.
     NewRound {
+        round: u32,
         city_name: String,
     },
+    /// Where the city of `round` was. Results for any other round than the one being played
+    /// are late, and can be ignored.
     RoundResults {
+        round: u32,
         actual_location: apricity::Coordinate,
     },
+    /// How long is left to guess. Sent with every new round, and to players who join mid-round.
+    RoundTimer {
+        remaining: std::time::Duration,
+    },
+    /// Every guess of `round`, which just ended, closest first.
+    RoundRankings {
+        round: u32,
+        rankings: Vec<RoundScore>,
+    },
+    /// Totals over every round played in the room, highest first.
+    Leaderboard {
+        standings: Vec<Standing>,
+    },
+    RoomList {
+        rooms: Vec<RoomInfo>,
+    },
+    /// Followed by the room's current round, like after `Welcome`.
+    JoinedRoom {
+        name: String,
+    },
+    LeftRoom,
+    /// A room request that couldn't be done, e.g. joining a room that doesn't exist.
+    RoomError {
+        message: String,
+    },
+    /// Someone who is connected already has the name from `Hello`. Say `Hello` again with
+    /// another name before joining a room.
+    NameTaken {
+        name: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RoundScore {
+    pub name: String,
+    pub distance_km: f64,
+    pub points: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Standing {
+    pub name: String,
+    pub points: u64,
+    pub rounds: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RoomInfo {
+    pub name: String,
+    pub players: usize,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -16,5 +74,23 @@ pub enum ClientMessage {
     Hello {
         name: String,
     },
-    Guess(apricity::Coordinate),
+    /// A guess for the city of `round`. Guesses for any other round are ignored, so a guess
+    /// sent just as the round ended isn't taken as one for the next city.
+    Guess {
+        round: u32,
+        coordinate: apricity::Coordinate,
+    },
+    ListRooms,
+    /// Creates a room and moves the player to it. Without a policy, the room picks cities
+    /// the way the server does by default.
+    CreateRoom {
+        name: String,
+        policy: Option<crate::selection::SelectionPolicy>,
+    },
+    /// Moves the player to another room. Asking for the room they are already in is a `RoomError`.
+    JoinRoom {
+        name: String,
+    },
+    /// Leaves the current room without joining another.
+    LeaveRoom,
 }
\ No newline at end of file
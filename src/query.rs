@@ -0,0 +1,541 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+
+use regex::Regex;
+
+use crate::names::{names_of, normalize_name};
+use crate::CityData;
+
+/// A member of `CityData` that queries can filter and sort on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Field {
+    Name,
+    AsciiName,
+    GeonameId,
+    CountryCode,
+    CountryName,
+    Admin1Code,
+    Admin2Code,
+    Admin3Code,
+    Admin4Code,
+    FeatureClass,
+    FeatureCode,
+    Timezone,
+    ModificationDate,
+    Population,
+    Dem,
+    Latitude,
+    Longitude,
+}
+
+impl Field {
+    pub const ALL: [Field; 17] = [
+        Field::Name,
+        Field::AsciiName,
+        Field::GeonameId,
+        Field::CountryCode,
+        Field::CountryName,
+        Field::Admin1Code,
+        Field::Admin2Code,
+        Field::Admin3Code,
+        Field::Admin4Code,
+        Field::FeatureClass,
+        Field::FeatureCode,
+        Field::Timezone,
+        Field::ModificationDate,
+        Field::Population,
+        Field::Dem,
+        Field::Latitude,
+        Field::Longitude,
+    ];
+
+    /// The name of the `CityData` member this field reads.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::AsciiName => "ascii_name",
+            Field::GeonameId => "geoname_id",
+            Field::CountryCode => "country_code",
+            Field::CountryName => "cou_name_en",
+            Field::Admin1Code => "admin1_code",
+            Field::Admin2Code => "admin2_code",
+            Field::Admin3Code => "admin3_code",
+            Field::Admin4Code => "admin4_code",
+            Field::FeatureClass => "feature_class",
+            Field::FeatureCode => "feature_code",
+            Field::Timezone => "timezone",
+            Field::ModificationDate => "modification_date",
+            Field::Population => "population",
+            Field::Dem => "dem",
+            Field::Latitude => "latitude",
+            Field::Longitude => "longitude",
+        }
+    }
+
+    /// Whether the field holds a number rather than text.
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Field::GeonameId | Field::Population | Field::Dem | Field::Latitude | Field::Longitude)
+    }
+
+    /// Brings text to compare against the field into the form the field is stored in.
+    /// Country codes are stored in capitals, but "us" should find them too.
+    fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            Field::CountryCode => Cow::Owned(text.to_ascii_uppercase()),
+            _ => Cow::Borrowed(text),
+        }
+    }
+
+    pub fn value<'a>(&self, city: &'a CityData) -> Value<'a> {
+        let text = |x: &'a str| Value::Text(Cow::Borrowed(x));
+        let optional = |x: Option<&'a str>| match x {
+            Some(x) => Value::Text(Cow::Borrowed(x)),
+            None => Value::Missing,
+        };
+        match self {
+            Field::Name => text(&city.name),
+            Field::AsciiName => optional(city.ascii_name.as_deref()),
+            Field::GeonameId => Value::Number(city.geoname_id.0 as f64),
+            Field::CountryCode => optional(city.country_code.as_ref().map(|x| x.as_str())),
+            Field::CountryName => optional(city.cou_name_en.as_deref()),
+            Field::Admin1Code => optional(city.admin1_code.as_deref()),
+            Field::Admin2Code => optional(city.admin2_code.as_deref()),
+            Field::Admin3Code => optional(city.admin3_code.as_deref()),
+            Field::Admin4Code => optional(city.admin4_code.as_deref()),
+            Field::FeatureClass => optional(city.feature_class.map(|x| x.as_str())),
+            Field::FeatureCode => optional(city.feature_code.as_ref().map(|x| x.as_str())),
+            Field::Timezone => text(&city.timezone),
+            Field::ModificationDate => Value::Text(Cow::Owned(city.modification_date.to_string())),
+            Field::Population => Value::Number(city.population as f64),
+            Field::Dem => Value::Number(city.dem as f64),
+            Field::Latitude => Value::Number(city.coordinates.latitude),
+            Field::Longitude => Value::Number(city.coordinates.longitude),
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The value of a `Field` for one city.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value<'a> {
+    Number(f64),
+    Text(Cow<'a, str>),
+    /// The city doesn't have this field, e.g. no `admin2_code`.
+    Missing,
+}
+
+impl Value<'_> {
+    /// Orders numbers numerically and text lexicographically. Missing values sort last.
+    fn sort_cmp(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a.total_cmp(b),
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+            (Value::Missing, Value::Missing) => Ordering::Equal,
+            (Value::Missing, _) => Ordering::Greater,
+            (_, Value::Missing) => Ordering::Less,
+            (Value::Number(_), Value::Text(_)) => Ordering::Less,
+            (Value::Text(_), Value::Number(_)) => Ordering::Greater,
+        }
+    }
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(x) => write!(f, "{}", x),
+            Value::Text(x) => f.write_str(x),
+            Value::Missing => Ok(()),
+        }
+    }
+}
+
+/// A constant to compare a field against.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal {
+    Number(f64),
+    Text(String),
+}
+
+impl From<f64> for Literal {
+    fn from(value: f64) -> Self {
+        Literal::Number(value)
+    }
+}
+
+impl From<i64> for Literal {
+    fn from(value: i64) -> Self {
+        Literal::Number(value as f64)
+    }
+}
+
+impl From<&str> for Literal {
+    fn from(value: &str) -> Self {
+        Literal::Text(value.to_string())
+    }
+}
+
+impl From<String> for Literal {
+    fn from(value: String) -> Self {
+        Literal::Text(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Greater => ordering == Ordering::Greater,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// A test on a single field of a city.
+#[derive(Clone, Debug)]
+pub enum Predicate {
+    /// Numbers compare numerically, text lexicographically (which also orders dates).
+    /// A number never matches text, and a missing field never matches.
+    Compare(Field, Comparison, Literal),
+    EqualsIgnoreCase(Field, String),
+    StartsWith(Field, String),
+    Contains(Field, String),
+    Matches(Field, Regex),
+    /// Any of the city's names, as in `NameIndex`, is this one. Kept in `normalize_name` form.
+    KnownAs(String),
+}
+
+impl Predicate {
+    pub fn matches(&self, city: &CityData) -> bool {
+        match self {
+            Predicate::Compare(field, comparison, literal) => {
+                let ordering = match (field.value(city), literal) {
+                    (Value::Number(a), Literal::Number(b)) => a.partial_cmp(b),
+                    (Value::Text(a), Literal::Text(b)) => Some(a.as_ref().cmp(&field.normalize(b))),
+                    _ => None,
+                };
+                ordering.is_some_and(|x| comparison.holds(x))
+            }
+            Predicate::EqualsIgnoreCase(field, text) => match field.value(city) {
+                Value::Text(x) => x.to_lowercase() == text.to_lowercase(),
+                _ => false,
+            },
+            Predicate::StartsWith(field, prefix) => match field.value(city) {
+                Value::Text(x) => x.starts_with(field.normalize(prefix).as_ref()),
+                _ => false,
+            },
+            Predicate::Contains(field, text) => match field.value(city) {
+                Value::Text(x) => x.contains(field.normalize(text).as_ref()),
+                _ => false,
+            },
+            Predicate::Matches(field, regex) => match field.value(city) {
+                Value::Text(x) => regex.is_match(&x),
+                Value::Number(x) => regex.is_match(&x.to_string()),
+                Value::Missing => false,
+            },
+            Predicate::KnownAs(name) => names_of(city).any(|x| normalize_name(x) == *name),
+        }
+    }
+}
+
+/// A boolean expression over predicates.
+///
+/// `&`, `|` and `!` combine filters, so the California query from exercise 1 is
+/// `CityFilter::equals(Field::CountryCode, "US") & CityFilter::equals(Field::Admin1Code, "CA")
+/// & CityFilter::compare(Field::Population, Comparison::Greater, 1_000_000)`.
+#[derive(Clone, Debug)]
+pub enum CityFilter {
+    /// Matches every city.
+    All,
+    Predicate(Predicate),
+    And(Vec<CityFilter>),
+    Or(Vec<CityFilter>),
+    Not(Box<CityFilter>),
+}
+
+impl CityFilter {
+    pub fn compare(field: Field, comparison: Comparison, value: impl Into<Literal>) -> CityFilter {
+        CityFilter::Predicate(Predicate::Compare(field, comparison, value.into()))
+    }
+
+    pub fn equals(field: Field, value: impl Into<Literal>) -> CityFilter {
+        CityFilter::compare(field, Comparison::Equal, value)
+    }
+
+    pub fn equals_ignore_case(field: Field, text: &str) -> CityFilter {
+        CityFilter::Predicate(Predicate::EqualsIgnoreCase(field, text.to_string()))
+    }
+
+    pub fn starts_with(field: Field, prefix: &str) -> CityFilter {
+        CityFilter::Predicate(Predicate::StartsWith(field, prefix.to_string()))
+    }
+
+    pub fn contains(field: Field, text: &str) -> CityFilter {
+        CityFilter::Predicate(Predicate::Contains(field, text.to_string()))
+    }
+
+    /// Matches cities with `name` as any of their names, ignoring case and accents, so
+    /// "Goettingen" and "gottingen" both find Göttingen.
+    pub fn known_as(name: &str) -> CityFilter {
+        CityFilter::Predicate(Predicate::KnownAs(normalize_name(name)))
+    }
+
+    pub fn matches_regex(field: Field, pattern: &str) -> Result<CityFilter, regex::Error> {
+        Ok(CityFilter::Predicate(Predicate::Matches(field, Regex::new(pattern)?)))
+    }
+
+    /// Matches when both filters match. Nested `And`s are flattened.
+    pub fn and(self, other: CityFilter) -> CityFilter {
+        match (self, other) {
+            (CityFilter::All, x) | (x, CityFilter::All) => x,
+            (CityFilter::And(mut a), CityFilter::And(b)) => {
+                a.extend(b);
+                CityFilter::And(a)
+            }
+            (CityFilter::And(mut a), b) => {
+                a.push(b);
+                CityFilter::And(a)
+            }
+            (a, b) => CityFilter::And(vec![a, b]),
+        }
+    }
+
+    /// Matches when either filter matches. Nested `Or`s are flattened.
+    pub fn or(self, other: CityFilter) -> CityFilter {
+        match (self, other) {
+            (CityFilter::All, _) | (_, CityFilter::All) => CityFilter::All,
+            (CityFilter::Or(mut a), CityFilter::Or(b)) => {
+                a.extend(b);
+                CityFilter::Or(a)
+            }
+            (CityFilter::Or(mut a), b) => {
+                a.push(b);
+                CityFilter::Or(a)
+            }
+            (a, b) => CityFilter::Or(vec![a, b]),
+        }
+    }
+
+    pub fn matches(&self, city: &CityData) -> bool {
+        match self {
+            CityFilter::All => true,
+            CityFilter::Predicate(predicate) => predicate.matches(city),
+            CityFilter::And(filters) => filters.iter().all(|x| x.matches(city)),
+            CityFilter::Or(filters) => filters.iter().any(|x| x.matches(city)),
+            CityFilter::Not(filter) => !filter.matches(city),
+        }
+    }
+}
+
+impl std::ops::BitAnd for CityFilter {
+    type Output = CityFilter;
+
+    fn bitand(self, other: CityFilter) -> CityFilter {
+        self.and(other)
+    }
+}
+
+impl std::ops::BitOr for CityFilter {
+    type Output = CityFilter;
+
+    fn bitor(self, other: CityFilter) -> CityFilter {
+        self.or(other)
+    }
+}
+
+impl std::ops::Not for CityFilter {
+    type Output = CityFilter;
+
+    fn not(self) -> CityFilter {
+        match self {
+            CityFilter::Not(x) => *x,
+            x => CityFilter::Not(Box::new(x)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// A filter plus the order and number of results to return.
+#[derive(Clone, Debug)]
+pub struct CityQuery {
+    pub filter: CityFilter,
+    pub order_by: Vec<(Field, SortOrder)>,
+    pub limit: Option<usize>,
+}
+
+impl Default for CityQuery {
+    fn default() -> Self {
+        CityQuery::new()
+    }
+}
+
+impl CityQuery {
+    /// A query that returns every city, in the order given.
+    pub fn new() -> CityQuery {
+        CityQuery {
+            filter: CityFilter::All,
+            order_by: Vec::new(),
+            limit: None,
+        }
+    }
+
+    /// Adds `filter` to the filters that must match.
+    pub fn filter(mut self, filter: CityFilter) -> CityQuery {
+        self.filter = std::mem::replace(&mut self.filter, CityFilter::All).and(filter);
+        self
+    }
+
+    /// Sorts by `field`, after any earlier sort keys.
+    pub fn order_by(mut self, field: Field, order: SortOrder) -> CityQuery {
+        self.order_by.push((field, order));
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> CityQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn run<'a>(&self, cities: impl IntoIterator<Item = &'a CityData>) -> Vec<&'a CityData> {
+        let mut result = cities
+            .into_iter()
+            .filter(|x| self.filter.matches(x))
+            .collect::<Vec<_>>();
+        if !self.order_by.is_empty() {
+            result.sort_by(|a, b| self.compare(a, b));
+        }
+        if let Some(limit) = self.limit {
+            result.truncate(limit);
+        }
+        result
+    }
+
+    fn compare(&self, a: &CityData, b: &CityData) -> Ordering {
+        for (field, order) in &self.order_by {
+            let (a, b) = (field.value(a), field.value(b));
+            let ordering = a.sort_cmp(&b);
+            // Missing values stay last whichever way the rest is sorted
+            let missing = a == Value::Missing || b == Value::Missing;
+            let ordering = match order {
+                SortOrder::Descending if !missing => ordering.reverse(),
+                _ => ordering,
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn city(name: &str, country_code: &str, admin1_code: &str, population: i64) -> CityData {
+        test_support::city(name).country(country_code).admin1(admin1_code).population(population).build()
+    }
+
+    fn cities() -> Vec<CityData> {
+        vec![
+            city("San Diego", "US", "CA", 1_394_928),
+            city("Los Angeles", "US", "CA", 3_971_883),
+            city("Sacramento", "US", "CA", 490_712),
+            city("Phoenix", "US", "AZ", 1_563_025),
+            city("Stockholm", "SE", "26", 1_515_017),
+        ]
+    }
+
+    fn names(result: Vec<&CityData>) -> Vec<&str> {
+        result.iter().map(|x| x.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_and_chain() {
+        let cities = cities();
+        let query = CityQuery::new()
+            .filter(CityFilter::equals(Field::CountryCode, "US"))
+            .filter(CityFilter::equals(Field::Admin1Code, "CA"))
+            .filter(CityFilter::compare(Field::Population, Comparison::Greater, 1_000_000));
+        assert_eq!(names(query.run(&cities)), ["San Diego", "Los Angeles"]);
+    }
+
+    #[test]
+    fn test_or_not() {
+        let cities = cities();
+        let filter = (CityFilter::equals(Field::Admin1Code, "AZ") | CityFilter::equals(Field::CountryCode, "SE"))
+            & !CityFilter::starts_with(Field::Name, "Stock");
+        let query = CityQuery::new().filter(filter);
+        assert_eq!(names(query.run(&cities)), ["Phoenix"]);
+    }
+
+    #[test]
+    fn test_text_predicates() {
+        let cities = cities();
+        let query = CityQuery::new().filter(CityFilter::equals_ignore_case(Field::Name, "SACRAMENTO"));
+        assert_eq!(names(query.run(&cities)), ["Sacramento"]);
+        let query = CityQuery::new().filter(CityFilter::matches_regex(Field::Name, "^S.*o$").unwrap());
+        assert_eq!(names(query.run(&cities)), ["San Diego", "Sacramento"]);
+        let query = CityQuery::new().filter(CityFilter::known_as("san  DIEGO"));
+        assert_eq!(names(query.run(&cities)), ["San Diego"]);
+        // Numbers never equal text
+        let query = CityQuery::new().filter(CityFilter::equals(Field::Population, "490712"));
+        assert!(query.run(&cities).is_empty());
+    }
+
+    #[test]
+    fn test_country_code_ignores_case() {
+        let cities = cities();
+        let query = CityQuery::new().filter(CityFilter::equals(Field::CountryCode, "se"));
+        assert_eq!(names(query.run(&cities)), ["Stockholm"]);
+        let query = CityQuery::new().filter(CityFilter::starts_with(Field::CountryCode, "u"));
+        assert_eq!(query.run(&cities).len(), 4);
+    }
+
+    #[test]
+    fn test_order_and_limit() {
+        let cities = cities();
+        let query = CityQuery::new()
+            .order_by(Field::CountryCode, SortOrder::Descending)
+            .order_by(Field::Population, SortOrder::Descending)
+            .limit(3);
+        assert_eq!(names(query.run(&cities)), ["Los Angeles", "Phoenix", "San Diego"]);
+    }
+
+    #[test]
+    fn test_missing_values_sort_last() {
+        let mut cities = cities();
+        cities[0].admin2_code = Some("073".to_string());
+        cities[1].admin2_code = Some("037".to_string());
+        for order in [SortOrder::Ascending, SortOrder::Descending] {
+            let result = CityQuery::new().order_by(Field::Admin2Code, order).limit(2).run(&cities);
+            assert!(result.iter().all(|x| x.admin2_code.is_some()), "{:?}", order);
+        }
+        let query = CityQuery::new().order_by(Field::Admin2Code, SortOrder::Descending).limit(2);
+        assert_eq!(names(query.run(&cities)), ["San Diego", "Los Angeles"]);
+    }
+}
@@ -0,0 +1,456 @@
+//! Text syntax for `CityQuery`, e.g.
+//!
+//! ```text
+//! country=US and admin1=CA and population>1000000 order by population desc limit 10
+//! ```
+//!
+//! * Conditions are `field operator value`. Fields are the `CityData` member names
+//!   (`country_code`, `admin1_code`, ...) or the short names `country`, `admin1`..`admin4`,
+//!   `class`, `code`, `lat`, `lon` and `elevation`.
+//! * Operators: `=` `!=` `<` `<=` `>` `>=`, `^=` (starts with), `*=` (contains),
+//!   `~=` (equal ignoring case) and `~` (regular expression).
+//! * `name=` and `name!=` look at every name of a city and ignore case and accents, so
+//!   `name=Goettingen` finds Göttingen.
+//! * Values are bare words (`US`, `Europe/Stockholm`, `2020-01-01`) or `"quoted strings"`.
+//!   Country codes can be written in any case, `country=us` finds the same cities as `country=US`.
+//! * Conditions combine with `and`, `or`, `not` and parentheses; `not` binds tightest, then `and`.
+//! * The filter is optional and may be followed by `order by field [asc|desc], ...` and `limit n`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::query::{CityFilter, CityQuery, Comparison, Field, Literal, Predicate, SortOrder};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryParseError {
+    /// Position of the offending token, counted in characters from 1.
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for QueryParseError {}
+
+impl FromStr for CityQuery {
+    type Err = QueryParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_query(s)
+    }
+}
+
+impl Field {
+    /// Looks up a field by its `CityData` member name or one of the short names.
+    pub fn from_name(name: &str) -> Option<Field> {
+        let name = name.to_ascii_lowercase();
+        let field = match name.as_str() {
+            "country" => Field::CountryCode,
+            "country_name" => Field::CountryName,
+            "admin1" => Field::Admin1Code,
+            "admin2" => Field::Admin2Code,
+            "admin3" => Field::Admin3Code,
+            "admin4" => Field::Admin4Code,
+            "class" => Field::FeatureClass,
+            "code" => Field::FeatureCode,
+            "lat" => Field::Latitude,
+            "lon" | "lng" => Field::Longitude,
+            "elevation" => Field::Dem,
+            _ => return Field::ALL.into_iter().find(|x| x.name() == name),
+        };
+        Some(field)
+    }
+}
+
+pub fn parse_query(input: &str) -> Result<CityQuery, QueryParseError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+    };
+
+    let mut query = CityQuery::new();
+    if !parser.at_keyword("order") && !parser.at_keyword("limit") && !parser.at_end() {
+        query = query.filter(parser.parse_or()?);
+    }
+    if parser.at_keyword("order") {
+        parser.advance();
+        parser.expect_keyword("by")?;
+        loop {
+            let field = parser.parse_field()?;
+            let order = if parser.at_keyword("desc") {
+                parser.advance();
+                SortOrder::Descending
+            } else {
+                if parser.at_keyword("asc") {
+                    parser.advance();
+                }
+                SortOrder::Ascending
+            };
+            query = query.order_by(field, order);
+            if parser.peek().kind != TokenKind::Comma {
+                break;
+            }
+            parser.advance();
+        }
+    }
+    if parser.at_keyword("limit") {
+        parser.advance();
+        let token = parser.advance();
+        let limit = match &token.kind {
+            TokenKind::Word(x) => x.parse::<usize>().ok(),
+            _ => None,
+        };
+        match limit {
+            Some(limit) => query = query.limit(limit),
+            None => return Err(token.error("expected a number after `limit`")),
+        }
+    }
+    if !parser.at_end() {
+        let token = parser.peek();
+        return Err(token.error(&format!("unexpected {}", token.kind)));
+    }
+    Ok(query)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    /// Anything unquoted: keywords, field names and bare values.
+    Word(String),
+    Quoted(String),
+    Operator(&'static str),
+    OpenParen,
+    CloseParen,
+    Comma,
+    End,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Word(x) => write!(f, "`{}`", x),
+            TokenKind::Quoted(x) => write!(f, "{:?}", x),
+            TokenKind::Operator(x) => write!(f, "`{}`", x),
+            TokenKind::OpenParen => write!(f, "`(`"),
+            TokenKind::CloseParen => write!(f, "`)`"),
+            TokenKind::Comma => write!(f, "`,`"),
+            TokenKind::End => write!(f, "end of query"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: &str) -> QueryParseError {
+        QueryParseError {
+            column: self.column,
+            message: message.to_string(),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.kind, TokenKind::Word(x) if x.eq_ignore_ascii_case(keyword))
+    }
+}
+
+// Longest first, so `<=` isn't read as `<` followed by `=`.
+const OPERATORS: [&str; 10] = ["!=", "<=", ">=", "^=", "*=", "~=", "=", "<", ">", "~"];
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '=' | '!' | '<' | '>' | '^' | '*' | '~' | '(' | ')' | ',' | '"')
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, QueryParseError> {
+    let chars = input.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let kind = match c {
+            '(' => {
+                i += 1;
+                TokenKind::OpenParen
+            }
+            ')' => {
+                i += 1;
+                TokenKind::CloseParen
+            }
+            ',' => {
+                i += 1;
+                TokenKind::Comma
+            }
+            '"' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            return Err(QueryParseError {
+                                column,
+                                message: "unterminated string".to_string(),
+                            })
+                        }
+                        Some('"') => break,
+                        // Only quotes and backslashes are escaped, so regexes like "\d+" read as written
+                        Some('\\') if matches!(chars.get(i + 1), Some('"' | '\\')) => {
+                            text.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(x) => {
+                            text.push(*x);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+                TokenKind::Quoted(text)
+            }
+            _ if is_word_char(c) => {
+                let start = i;
+                while i < chars.len() && is_word_char(chars[i]) {
+                    i += 1;
+                }
+                TokenKind::Word(chars[start..i].iter().collect())
+            }
+            _ => {
+                let rest = chars[i..].iter().take(2).collect::<String>();
+                match OPERATORS.iter().find(|x| rest.starts_with(*x)) {
+                    Some(operator) => {
+                        i += operator.len();
+                        TokenKind::Operator(operator)
+                    }
+                    None => {
+                        return Err(QueryParseError {
+                            column,
+                            message: format!("unexpected `{}`", c),
+                        })
+                    }
+                }
+            }
+        };
+        tokens.push(Token { kind, column });
+    }
+    tokens.push(Token {
+        kind: TokenKind::End,
+        column: chars.len() + 1,
+    });
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position]
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.position].clone();
+        if token.kind != TokenKind::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn at_end(&self) -> bool {
+        self.peek().kind == TokenKind::End
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        self.peek().is_keyword(keyword)
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), QueryParseError> {
+        let token = self.advance();
+        if token.is_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(token.error(&format!("expected `{}`, found {}", keyword, token.kind)))
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<CityFilter, QueryParseError> {
+        let mut filter = self.parse_and()?;
+        while self.at_keyword("or") {
+            self.advance();
+            filter = filter.or(self.parse_and()?);
+        }
+        Ok(filter)
+    }
+
+    fn parse_and(&mut self) -> Result<CityFilter, QueryParseError> {
+        let mut filter = self.parse_not()?;
+        while self.at_keyword("and") {
+            self.advance();
+            filter = filter.and(self.parse_not()?);
+        }
+        Ok(filter)
+    }
+
+    fn parse_not(&mut self) -> Result<CityFilter, QueryParseError> {
+        if self.at_keyword("not") {
+            self.advance();
+            return Ok(!self.parse_not()?);
+        }
+        if self.peek().kind == TokenKind::OpenParen {
+            self.advance();
+            let filter = self.parse_or()?;
+            let token = self.advance();
+            if token.kind != TokenKind::CloseParen {
+                return Err(token.error(&format!("expected `)`, found {}", token.kind)));
+            }
+            return Ok(filter);
+        }
+        self.parse_condition()
+    }
+
+    fn parse_field(&mut self) -> Result<Field, QueryParseError> {
+        let token = self.advance();
+        match &token.kind {
+            TokenKind::Word(name) => Field::from_name(name)
+                .ok_or_else(|| token.error(&format!("unknown field `{}`", name))),
+            kind => Err(token.error(&format!("expected a field name, found {}", kind))),
+        }
+    }
+
+    fn parse_condition(&mut self) -> Result<CityFilter, QueryParseError> {
+        let field = self.parse_field()?;
+        let operator_token = self.advance();
+        let operator = match &operator_token.kind {
+            TokenKind::Operator(x) => *x,
+            kind => return Err(operator_token.error(&format!("expected an operator after `{}`, found {}", field, kind))),
+        };
+        let value_token = self.advance();
+        let text = match &value_token.kind {
+            TokenKind::Word(x) | TokenKind::Quoted(x) => x.clone(),
+            kind => return Err(value_token.error(&format!("expected a value, found {}", kind))),
+        };
+
+        let comparison = match operator {
+            "=" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            ">" => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            _ => None,
+        };
+        match (field, comparison) {
+            (Field::Name, Some(Comparison::Equal)) => return Ok(CityFilter::known_as(&text)),
+            (Field::Name, Some(Comparison::NotEqual)) => return Ok(!CityFilter::known_as(&text)),
+            _ => {}
+        }
+        let predicate = match comparison {
+            Some(comparison) if field.is_numeric() => match text.replace('_', "").parse::<f64>() {
+                Ok(x) => Predicate::Compare(field, comparison, Literal::Number(x)),
+                Err(_) => return Err(value_token.error(&format!("`{}` needs a number", field))),
+            },
+            Some(comparison) => Predicate::Compare(field, comparison, Literal::Text(text)),
+            None if field.is_numeric() && operator != "~" => {
+                return Err(operator_token.error(&format!("`{}` can't be used on the number `{}`", operator, field)))
+            }
+            None => match operator {
+                "^=" => Predicate::StartsWith(field, text),
+                "*=" => Predicate::Contains(field, text),
+                "~=" => Predicate::EqualsIgnoreCase(field, text),
+                _ => match regex::Regex::new(&text) {
+                    Ok(regex) => Predicate::Matches(field, regex),
+                    Err(error) => return Err(value_token.error(&format!("invalid regular expression: {}", error))),
+                },
+            },
+        };
+        Ok(CityFilter::Predicate(predicate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_query() {
+        let query = parse_query("country=US and admin1=CA and population>1000000 order by population desc limit 10").unwrap();
+        match &query.filter {
+            CityFilter::And(filters) => assert_eq!(filters.len(), 3),
+            other => panic!("Expected an and, got {:?}", other),
+        }
+        assert_eq!(query.order_by, [(Field::Population, SortOrder::Descending)]);
+        assert_eq!(query.limit, Some(10));
+    }
+
+    #[test]
+    fn test_precedence() {
+        let query = parse_query(r#"not name ^= "San" and (timezone = Europe/Stockholm or dem >= 1_000)"#).unwrap();
+        match &query.filter {
+            CityFilter::And(filters) => {
+                assert!(matches!(filters[0], CityFilter::Not(_)));
+                assert!(matches!(&filters[1], CityFilter::Or(x) if x.len() == 2));
+            }
+            other => panic!("Expected an and, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_only_ordering() {
+        let query = parse_query("order by country asc, name").unwrap();
+        assert!(matches!(query.filter, CityFilter::All));
+        assert_eq!(query.order_by, [(Field::CountryCode, SortOrder::Ascending), (Field::Name, SortOrder::Ascending)]);
+        assert!(parse_query("").is_ok());
+    }
+
+    #[test]
+    fn test_geoname_id_and_country_values() {
+        let stockholm = crate::test_support::city("Stockholm").geoname_id(2673730).country("SE").build();
+        let matches = |input: &str| parse_query(input).unwrap().filter.matches(&stockholm);
+        assert!(matches("geoname_id=2673730"));
+        assert!(matches("geoname_id>2000000 and geoname_id<=2673730"));
+        assert!(parse_query("geoname_id=Stockholm").is_err());
+        assert!(matches("country=se"));
+        assert!(matches("country!=no and country^=s"));
+    }
+
+    #[test]
+    fn test_name_matches_any_name() {
+        let mut gottingen = crate::test_support::city("Göttingen").build();
+        gottingen.ascii_name = Some("Goettingen".to_string());
+        gottingen.alternate_names = vec!["Gottinga".to_string()];
+        let matches = |input: &str| parse_query(input).unwrap().filter.matches(&gottingen);
+        assert!(matches("name=Goettingen"));
+        assert!(matches("name=gottingen"));
+        assert!(matches("name=\"GOTTINGA\""));
+        assert!(!matches("name!=Göttingen"));
+        assert!(!matches("name=Kassel"));
+        // The other operators still look at `name` alone
+        assert!(!matches("name^=Goe"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |input: &str| parse_query(input).unwrap_err();
+        assert_eq!(error("country=US and size>3").column, 16);
+        assert_eq!(error("population>lots").column, 12);
+        assert_eq!(error("name=\"Göteborg").column, 6);
+        assert_eq!(error("(name=A").column, 8);
+        assert_eq!(error("name=A limit ten").column, 14);
+        assert_eq!(error("name ~ \"(\"").column, 8);
+        assert!(parse_query(r#"name ~ "^\d+ \"x\"$""#).is_ok());
+        assert_eq!(error("country=US order population").message, "expected `by`, found `population`");
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use apricity::Coordinate;
+
+use crate::protocol::{RoomInfo, RoundScore, ServerMessage};
+use crate::score::score_round;
+use crate::Leaderboard;
+
+/// The room every player starts in. It's never removed, even when empty.
+pub const DEFAULT_ROOM: &str = "main";
+
+/// Identifies a connected player, e.g. by socket.
+pub type PlayerId = u32;
+
+/// Messages for the server to send, and who to send each one to.
+pub type Outbox = Vec<(PlayerId, ServerMessage)>;
+
+/// One game of the guessing game: its players, the city they're guessing, their guesses and
+/// scores. A room doesn't know about sockets, it returns what should be sent instead.
+pub struct Room {
+    name: String,
+    round_time: Duration,
+    players: HashMap<PlayerId, String>,
+    /// 0 until the first `start_round`.
+    round: u32,
+    city_name: String,
+    actual_location: Coordinate,
+    guesses: HashMap<PlayerId, Coordinate>,
+    deadline: Instant,
+    leaderboard: Leaderboard,
+}
+
+impl Room {
+    /// A room without players. Call `start_round` before anyone joins.
+    pub fn new(name: &str, round_time: Duration, leaderboard: Leaderboard) -> Room {
+        Room {
+            name: name.to_string(),
+            round_time,
+            players: HashMap::new(),
+            round: 0,
+            city_name: String::new(),
+            actual_location: Coordinate { latitude: 0.0, longitude: 0.0 },
+            guesses: HashMap::new(),
+            deadline: Instant::now(),
+            leaderboard,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn round(&self) -> u32 {
+        self.round
+    }
+
+    pub fn city_name(&self) -> &str {
+        &self.city_name
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    pub fn contains(&self, player: PlayerId) -> bool {
+        self.players.contains_key(&player)
+    }
+
+    /// When the current round runs out of time.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn leaderboard(&self) -> &Leaderboard {
+        &self.leaderboard
+    }
+
+    pub fn info(&self) -> RoomInfo {
+        RoomInfo {
+            name: self.name.clone(),
+            players: self.players.len(),
+        }
+    }
+
+    fn broadcast(&self, messages: &[ServerMessage]) -> Outbox {
+        let mut players = self.players.keys().copied().collect::<Vec<_>>();
+        players.sort();
+        players
+            .into_iter()
+            .flat_map(|player| messages.iter().map(move |message| (player, message.clone())))
+            .collect()
+    }
+
+    /// The round in progress, as told to a player who just joined.
+    fn current_round(&self, now: Instant) -> Vec<ServerMessage> {
+        vec![
+            ServerMessage::NewRound { round: self.round, city_name: self.city_name.clone() },
+            ServerMessage::RoundTimer { remaining: self.deadline.saturating_duration_since(now) },
+            ServerMessage::Leaderboard { standings: self.leaderboard.standings() },
+        ]
+    }
+
+    /// Starts guessing a new city, forgetting any guesses for the last one.
+    pub fn start_round(&mut self, city_name: &str, actual_location: Coordinate, now: Instant) -> Outbox {
+        self.round += 1;
+        self.city_name = city_name.to_string();
+        self.actual_location = actual_location;
+        self.guesses.clear();
+        self.deadline = now + self.round_time;
+        self.broadcast(&[
+            ServerMessage::NewRound { round: self.round, city_name: self.city_name.clone() },
+            ServerMessage::RoundTimer { remaining: self.round_time },
+        ])
+    }
+
+    pub fn join(&mut self, player: PlayerId, name: &str, now: Instant) -> Outbox {
+        // Nobody has been playing, so the clock starts over for whoever comes in
+        if self.players.is_empty() {
+            self.deadline = now + self.round_time;
+        }
+        self.players.insert(player, name.to_string());
+        let mut messages = vec![ServerMessage::JoinedRoom { name: self.name.clone() }];
+        messages.extend(self.current_round(now));
+        messages.into_iter().map(|x| (player, x)).collect()
+    }
+
+    /// Removes the player and their guess. Returns whether they were in the room.
+    pub fn leave(&mut self, player: PlayerId) -> bool {
+        self.guesses.remove(&player);
+        self.players.remove(&player).is_some()
+    }
+
+    /// Only the first guess of a round counts. Guesses from players who aren't in the room,
+    /// or for a round other than the current one, are ignored.
+    pub fn guess(&mut self, player: PlayerId, round: u32, coordinate: Coordinate) {
+        if self.players.contains_key(&player) && round == self.round {
+            self.guesses.entry(player).or_insert(coordinate);
+        }
+    }
+
+    pub fn guess_count(&self) -> usize {
+        self.guesses.len()
+    }
+
+    pub fn all_guessed(&self) -> bool {
+        !self.players.is_empty() && self.guesses.len() == self.players.len()
+    }
+
+    /// Whether it's time for `end_round`: everyone has guessed, or time is up for a room
+    /// that has players.
+    pub fn is_round_over(&self, now: Instant) -> bool {
+        self.all_guessed() || (!self.players.is_empty() && now >= self.deadline)
+    }
+
+    /// Scores the guesses, adds them to the leaderboard and tells everyone how it went.
+    /// Players who didn't guess in time get no points for the round.
+    pub fn end_round(&mut self) -> (Vec<RoundScore>, Outbox) {
+        let rankings = score_round(
+            self.actual_location,
+            self.guesses.iter().map(|(player, coordinate)| (self.players[player].as_str(), *coordinate)),
+        );
+        self.leaderboard.record(&rankings);
+        let outbox = self.broadcast(&[
+            ServerMessage::RoundResults { round: self.round, actual_location: self.actual_location },
+            ServerMessage::RoundRankings { round: self.round, rankings: rankings.clone() },
+            ServerMessage::Leaderboard { standings: self.leaderboard.standings() },
+        ]);
+        (rankings, outbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STOCKHOLM: Coordinate = Coordinate { latitude: 59.33, longitude: 18.07 };
+
+    fn room(now: Instant) -> Room {
+        let mut room = Room::new("test", Duration::from_secs(30), Leaderboard::new());
+        room.start_round("Stockholm", STOCKHOLM, now);
+        room
+    }
+
+    #[test]
+    fn test_round_ends_when_everyone_has_guessed() {
+        let now = Instant::now();
+        let mut room = room(now);
+        let joined = room.join(1, "Ada", now);
+        assert!(matches!(&joined[0], (1, ServerMessage::JoinedRoom { name }) if name == "test"));
+        assert!(matches!(&joined[1], (1, ServerMessage::NewRound { round: 1, city_name }) if city_name == "Stockholm"));
+        room.join(2, "Grace", now);
+
+        room.guess(1, 1, STOCKHOLM);
+        room.guess(3, 1, STOCKHOLM);
+        assert!(!room.is_round_over(now));
+        room.guess(2, 1, Coordinate { latitude: 0.0, longitude: 0.0 });
+        assert!(room.is_round_over(now));
+
+        let (rankings, outbox) = room.end_round();
+        assert_eq!(rankings.len(), 2);
+        assert_eq!(rankings[0].name, "Ada");
+        // Results, rankings and leaderboard to each of the two players
+        assert_eq!(outbox.len(), 6);
+        assert!(matches!(&outbox[0], (1, ServerMessage::RoundResults { round: 1, .. })));
+        assert!(matches!(&outbox[1], (1, ServerMessage::RoundRankings { round: 1, .. })));
+        assert_eq!(room.leaderboard().get("Ada").unwrap().rounds, 1);
+    }
+
+    #[test]
+    fn test_round_ends_on_deadline() {
+        let now = Instant::now();
+        let mut room = room(now);
+        room.join(1, "Ada", now);
+        room.join(2, "Grace", now);
+        room.guess(1, 1, STOCKHOLM);
+        assert!(!room.is_round_over(now + Duration::from_secs(29)));
+        assert!(room.is_round_over(now + Duration::from_secs(30)));
+        let (rankings, _) = room.end_round();
+        assert_eq!(rankings.len(), 1);
+        assert!(room.leaderboard().get("Grace").is_none());
+
+        // Leaving takes the guess with it, and an empty room never times out
+        let later = now + Duration::from_secs(60);
+        room.start_round("Stockholm", STOCKHOLM, later);
+        room.guess(1, 2, STOCKHOLM);
+        assert!(room.leave(1));
+        assert!(room.leave(2));
+        assert_eq!(room.guess_count(), 0);
+        assert!(!room.is_round_over(later + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_late_guess_is_ignored() {
+        let now = Instant::now();
+        let mut room = room(now);
+        room.join(1, "Ada", now);
+        room.join(2, "Grace", now);
+        room.guess(1, 1, STOCKHOLM);
+        room.end_round();
+
+        // Grace's guess for Stockholm arrives after the round moved on to Sydney
+        let outbox = room.start_round("Sydney", Coordinate { latitude: -33.87, longitude: 151.21 }, now);
+        assert!(matches!(&outbox[0], (1, ServerMessage::NewRound { round: 2, .. })));
+        room.guess(2, 1, STOCKHOLM);
+        assert_eq!(room.guess_count(), 0);
+        room.guess(2, 2, STOCKHOLM);
+        assert_eq!(room.guess_count(), 1);
+    }
+}
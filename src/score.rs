@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use apricity::Coordinate;
+
+use crate::protocol::{RoundScore, Standing};
+
+/// Points for a guess right on the city.
+pub const MAX_POINTS: u32 = 5000;
+/// How far off a guess can be and still get half of `MAX_POINTS`.
+pub const HALF_POINTS_KM: f64 = 1000.0;
+
+/// Points for a guess `distance_km` from the city, halving for every `HALF_POINTS_KM`.
+pub fn points(distance_km: f64) -> u32 {
+    (MAX_POINTS as f64 * 0.5f64.powf(distance_km.max(0.0) / HALF_POINTS_KM)).round() as u32
+}
+
+/// Scores each player's guess against where the city actually is, closest first.
+pub fn score_round<'a>(
+    actual_location: Coordinate,
+    guesses: impl IntoIterator<Item = (&'a str, Coordinate)>,
+) -> Vec<RoundScore> {
+    let mut rankings = guesses
+        .into_iter()
+        .map(|(name, guess)| {
+            let distance_km = actual_location.great_circle_distance(guess);
+            RoundScore {
+                name: name.to_string(),
+                distance_km,
+                points: points(distance_km),
+            }
+        })
+        .collect::<Vec<_>>();
+    rankings.sort_by(|a, b| a.distance_km.total_cmp(&b.distance_km).then_with(|| a.name.cmp(&b.name)));
+    rankings
+}
+
+/// Points per player, added up over rounds. Players are known by name, so someone who
+/// reconnects keeps their points, and two players playing at once need different names.
+#[derive(Clone, Debug, Default)]
+pub struct Leaderboard {
+    players: HashMap<String, Standing>,
+}
+
+impl Leaderboard {
+    pub fn new() -> Leaderboard {
+        Leaderboard::default()
+    }
+
+    /// Reads a leaderboard saved with `save`. A file that doesn't exist yet is an empty
+    /// leaderboard.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Leaderboard> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Leaderboard::new()),
+            Err(error) => return Err(error),
+        };
+        let standings = serde_json::from_str::<Vec<Standing>>(&json)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        let players = standings.into_iter().map(|x| (x.name.clone(), x)).collect();
+        Ok(Leaderboard { players })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.standings()).map_err(io::Error::other)?;
+        // Write to a temporary file first, so a crash while saving doesn't lose the old leaderboard.
+        let path = path.as_ref();
+        let mut temporary_path = path.as_os_str().to_owned();
+        temporary_path.push(".tmp");
+        fs::write(&temporary_path, json)?;
+        fs::rename(&temporary_path, path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Standing> {
+        self.players.get(name)
+    }
+
+    /// Adds the points from a round to each player's total.
+    pub fn record(&mut self, rankings: &[RoundScore]) {
+        for score in rankings {
+            let standing = self.players.entry(score.name.clone()).or_insert_with(|| Standing {
+                name: score.name.clone(),
+                points: 0,
+                rounds: 0,
+            });
+            standing.points += score.points as u64;
+            standing.rounds += 1;
+        }
+    }
+
+    /// Every player, most points first, then by name.
+    pub fn standings(&self) -> Vec<Standing> {
+        let mut standings = self.players.values().cloned().collect::<Vec<_>>();
+        standings.sort_by(|a, b| b.points.cmp(&a.points).then_with(|| a.name.cmp(&b.name)));
+        standings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(latitude: f64, longitude: f64) -> Coordinate {
+        Coordinate { latitude, longitude }
+    }
+
+    #[test]
+    fn test_points() {
+        assert_eq!(points(0.0), MAX_POINTS);
+        assert_eq!(points(HALF_POINTS_KM), MAX_POINTS / 2);
+        assert_eq!(points(2.0 * HALF_POINTS_KM), MAX_POINTS / 4);
+        assert_eq!(points(20_000.0), 0);
+        assert!(points(10.0) < points(5.0));
+    }
+
+    #[test]
+    fn test_leaderboard() {
+        let stockholm = at(59.33, 18.07);
+        let round = score_round(stockholm, [("far", at(-33.87, 151.21)), ("near", at(59.86, 17.64))]);
+        assert_eq!(round.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["near", "far"]);
+        assert!(round[0].distance_km > 60.0 && round[0].distance_km < 70.0);
+
+        let mut leaderboard = Leaderboard::new();
+        leaderboard.record(&round);
+        leaderboard.record(&score_round(stockholm, [("far", stockholm)]));
+        let standings = leaderboard.standings();
+        assert_eq!(standings[0].name, "far");
+        assert_eq!(standings[0].rounds, 2);
+        assert_eq!(standings[0].points, MAX_POINTS as u64 + round[1].points as u64);
+        assert_eq!(leaderboard.get("near").unwrap().rounds, 1);
+
+        let path = std::env::temp_dir().join(format!("rustdemo-leaderboard-{}.json", std::process::id()));
+        leaderboard.save(&path).unwrap();
+        let loaded = Leaderboard::load(&path).unwrap();
+        leaderboard.record(&score_round(stockholm, [("near", stockholm)]));
+        leaderboard.save(&path).unwrap();
+        assert_eq!(Leaderboard::load(&path).unwrap().get("near").unwrap().rounds, 2);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.standings(), standings);
+        assert!(Leaderboard::load(&path).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::{Rng, SeedableRng};
+
+use crate::{CityData, CountryCode, FeatureCode, GeonameId};
+
+/// The random numbers a game picks its cities with. Unlike `StdRng`, the numbers ChaCha gives
+/// for a seed are fixed, and `CitySelector::pick` turns them into cities with its own arithmetic
+/// instead of `rand`'s sampling, which may change between versions. So a seed means the same
+/// cities on every server.
+pub type SeededRng = rand_chacha::ChaCha8Rng;
+
+pub fn seeded_rng(seed: u64) -> SeededRng {
+    SeededRng::seed_from_u64(seed)
+}
+
+/// The seed of the daily challenge on the day `time` falls on, in UTC. It's the number of days
+/// since 1970-01-01, so everyone playing that day gets the same one.
+pub fn daily_seed(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs() / (24 * 60 * 60))
+}
+
+/// Presets for how well known the cities of a game are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Difficulty {
+    /// Capitals, mostly the big ones.
+    Easy,
+    /// Cities of at least 100 000 people, mostly the big ones.
+    Normal,
+    /// Any city at all.
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+}
+
+impl Display for Difficulty {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Difficulty::ALL
+            .into_iter()
+            .find(|x| x.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown difficulty {:?}", s))
+    }
+}
+
+/// A part of the world to pick cities from.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Region {
+    /// The first part of the timezone, e.g. `Europe` for `Europe/Stockholm`.
+    Continent(String),
+    Country(CountryCode),
+    Timezone(String),
+}
+
+impl Region {
+    pub fn contains(&self, city: &CityData) -> bool {
+        match self {
+            Region::Continent(continent) => city
+                .timezone
+                .split_once('/')
+                .is_some_and(|(prefix, _)| prefix.eq_ignore_ascii_case(continent)),
+            Region::Country(code) => city.country_code == Some(*code),
+            Region::Timezone(timezone) => city.timezone.eq_ignore_ascii_case(timezone),
+        }
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::Continent(continent) => write!(f, "continent:{}", continent),
+            Region::Country(code) => write!(f, "country:{}", code),
+            Region::Timezone(timezone) => write!(f, "timezone:{}", timezone),
+        }
+    }
+}
+
+/// Parses `continent:<name>`, `country:<code>` or `timezone:<name>`, e.g. `country:SE`.
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("{:?} should be continent:, country: or timezone: and a name", s))?;
+        if value.is_empty() {
+            return Err(format!("{:?} needs a name after the colon", s));
+        }
+        match kind {
+            "continent" => Ok(Region::Continent(value.to_string())),
+            "country" => value
+                .to_ascii_uppercase()
+                .parse()
+                .map(Region::Country)
+                .map_err(|error| format!("{}", error)),
+            "timezone" => Ok(Region::Timezone(value.to_string())),
+            other => Err(format!("unknown region kind {:?}", other)),
+        }
+    }
+}
+
+/// Whether bigger cities come up more often.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Weighting {
+    /// Every city is as likely as any other.
+    Uniform,
+    /// A city is picked in proportion to how many people live there.
+    Population,
+}
+
+/// Which cities a game picks from, and how. The default picks any city uniformly, repeats
+/// and all.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SelectionPolicy {
+    pub weighting: Weighting,
+    pub min_population: i64,
+    pub region: Option<Region>,
+    /// Only national capitals, `PPLC` in GeoNames.
+    pub capitals_only: bool,
+    /// Never the same city twice, until every city that fits has been played.
+    pub no_repeats: bool,
+}
+
+impl Default for SelectionPolicy {
+    fn default() -> Self {
+        SelectionPolicy {
+            weighting: Weighting::Uniform,
+            min_population: 0,
+            region: None,
+            capitals_only: false,
+            no_repeats: false,
+        }
+    }
+}
+
+impl SelectionPolicy {
+    pub fn for_difficulty(difficulty: Difficulty) -> SelectionPolicy {
+        match difficulty {
+            Difficulty::Easy => SelectionPolicy {
+                weighting: Weighting::Population,
+                capitals_only: true,
+                no_repeats: true,
+                ..SelectionPolicy::default()
+            },
+            Difficulty::Normal => SelectionPolicy {
+                weighting: Weighting::Population,
+                min_population: 100_000,
+                no_repeats: true,
+                ..SelectionPolicy::default()
+            },
+            Difficulty::Hard => SelectionPolicy {
+                no_repeats: true,
+                ..SelectionPolicy::default()
+            },
+        }
+    }
+
+    /// Whether the city may come up at all, regardless of what has been played.
+    pub fn allows(&self, city: &CityData) -> bool {
+        city.population >= self.min_population
+            && (!self.capitals_only || city.feature_code.as_ref().is_some_and(FeatureCode::is_capital))
+            && match &self.region {
+                Some(region) => region.contains(city),
+                None => true,
+            }
+    }
+}
+
+/// Picks the city for each round of a game, following a `SelectionPolicy`.
+pub struct CitySelector<'a> {
+    policy: SelectionPolicy,
+    candidates: Vec<&'a CityData>,
+    played: HashSet<GeonameId>,
+}
+
+impl<'a> CitySelector<'a> {
+    /// `None` if no city fits the policy.
+    pub fn new(cities: &'a [CityData], policy: SelectionPolicy) -> Option<CitySelector<'a>> {
+        let candidates = cities.iter().filter(|x| policy.allows(x)).collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(CitySelector {
+            policy,
+            candidates,
+            played: HashSet::new(),
+        })
+    }
+
+    pub fn policy(&self) -> &SelectionPolicy {
+        &self.policy
+    }
+
+    /// How many cities fit the policy.
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    fn remaining(&self) -> Vec<&'a CityData> {
+        self.candidates
+            .iter()
+            .copied()
+            .filter(|x| !self.played.contains(&x.geoname_id))
+            .collect()
+    }
+
+    /// Forgets which cities have been played, so the next `pick` can be any of them again.
+    pub fn restart(&mut self) {
+        self.played.clear();
+    }
+
+    /// The city for the next round. With `no_repeats`, once every city has been played the
+    /// game starts over from all of them.
+    ///
+    /// Only `next_u64` of `rng` is used, one number per pick, so the same numbers always give
+    /// the same cities.
+    pub fn pick<R: Rng + ?Sized>(&mut self, rng: &mut R) -> &'a CityData {
+        let mut remaining = self.remaining();
+        if remaining.is_empty() {
+            self.played.clear();
+            remaining = self.remaining();
+        }
+        let number = rng.next_u64();
+        let index = match self.policy.weighting {
+            // The bias of the modulo is negligible for any number of cities there is
+            Weighting::Uniform => (number % remaining.len() as u64) as usize,
+            // Places without a population still get a chance
+            Weighting::Population => {
+                let weight = |city: &CityData| city.population.max(1) as u64;
+                let mut target = number % remaining.iter().map(|x| weight(x)).sum::<u64>();
+                remaining
+                    .iter()
+                    .position(|x| {
+                        if target < weight(x) {
+                            return true;
+                        }
+                        target -= weight(x);
+                        false
+                    })
+                    .expect("the target is less than the total weight")
+            }
+        };
+        let city = remaining[index];
+        if self.policy.no_repeats {
+            self.played.insert(city.geoname_id);
+        }
+        city
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn city(name: &str, geoname_id: u64, feature_code: &str, population: i64, timezone: &str) -> CityData {
+        let country_code = if timezone.starts_with("Europe/") { "SE" } else { "JP" };
+        test_support::city(name)
+            .geoname_id(geoname_id)
+            .feature_code(feature_code)
+            .population(population)
+            .country(country_code)
+            .timezone(timezone)
+            .build()
+    }
+
+    fn cities() -> Vec<CityData> {
+        vec![
+            city("Stockholm", 1, "PPLC", 1_515_017, "Europe/Stockholm"),
+            city("Uppsala", 2, "PPLA", 133_117, "Europe/Stockholm"),
+            city("Knivsta", 3, "PPL", 9_000, "Europe/Stockholm"),
+            city("Tokyo", 4, "PPLC", 8_336_599, "Asia/Tokyo"),
+            city("Osaka", 5, "PPLA", 2_592_413, "Asia/Tokyo"),
+        ]
+    }
+
+    fn names<'a>(selector: &CitySelector<'a>) -> Vec<&'a str> {
+        let mut names = selector.candidates.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_policy_filters() {
+        let cities = cities();
+        let easy = CitySelector::new(&cities, SelectionPolicy::for_difficulty(Difficulty::Easy)).unwrap();
+        assert_eq!(names(&easy), ["Stockholm", "Tokyo"]);
+        let normal = CitySelector::new(&cities, SelectionPolicy::for_difficulty(Difficulty::Normal)).unwrap();
+        assert_eq!(names(&normal), ["Osaka", "Stockholm", "Tokyo", "Uppsala"]);
+
+        let europe = SelectionPolicy {
+            region: Some("continent:europe".parse().unwrap()),
+            ..SelectionPolicy::default()
+        };
+        assert_eq!(names(&CitySelector::new(&cities, europe).unwrap()), ["Knivsta", "Stockholm", "Uppsala"]);
+        let japan = SelectionPolicy {
+            region: Some("country:jp".parse().unwrap()),
+            capitals_only: true,
+            ..SelectionPolicy::default()
+        };
+        assert_eq!(names(&CitySelector::new(&cities, japan).unwrap()), ["Tokyo"]);
+
+        let nowhere = SelectionPolicy {
+            region: Some(Region::Timezone("Africa/Cairo".to_string())),
+            ..SelectionPolicy::default()
+        };
+        assert!(CitySelector::new(&cities, nowhere).is_none());
+        assert!("planet:Mars".parse::<Region>().is_err());
+        assert_eq!("HARD".parse(), Ok(Difficulty::Hard));
+    }
+
+    #[test]
+    fn test_no_repeats_until_every_city_is_played() {
+        let cities = cities();
+        let mut selector = CitySelector::new(&cities, SelectionPolicy::for_difficulty(Difficulty::Hard)).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut played = (0..5).map(|_| selector.pick(&mut rng).geoname_id).collect::<Vec<_>>();
+        played.sort();
+        played.dedup();
+        assert_eq!(played.len(), 5);
+        // And then it starts over
+        selector.pick(&mut rng);
+        assert_eq!(selector.played.len(), 1);
+        selector.restart();
+        assert!(selector.played.is_empty());
+    }
+
+    #[test]
+    fn test_same_seed_same_cities() {
+        let cities = cities();
+        let sequence = |seed| {
+            let mut selector = CitySelector::new(&cities, SelectionPolicy::default()).unwrap();
+            let mut rng = seeded_rng(seed);
+            (0..20).map(|_| selector.pick(&mut rng).geoname_id.0).collect::<Vec<_>>()
+        };
+        assert_eq!(sequence(2024), sequence(2024));
+        assert_ne!(sequence(2024), sequence(2025));
+        // Pinned, so a seed keeps meaning the same cities whatever version of rand is used
+        assert_eq!(sequence(2024)[..10], [2, 4, 2, 5, 4, 2, 2, 1, 1, 3]);
+
+        let day = UNIX_EPOCH + std::time::Duration::from_secs(19_000 * 24 * 60 * 60);
+        assert_eq!(daily_seed(day), 19_000);
+        assert_eq!(daily_seed(day + std::time::Duration::from_secs(23 * 60 * 60)), 19_000);
+    }
+
+    #[test]
+    fn test_population_weighting() {
+        let cities = vec![
+            city("Tokyo", 4, "PPLC", 8_000_000, "Asia/Tokyo"),
+            city("Knivsta", 3, "PPL", 8_000, "Europe/Stockholm"),
+        ];
+        let policy = SelectionPolicy {
+            weighting: Weighting::Population,
+            ..SelectionPolicy::default()
+        };
+        let mut selector = CitySelector::new(&cities, policy).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let tokyo = (0..1000).filter(|_| selector.pick(&mut rng).name == "Tokyo").count();
+        assert!(tokyo > 980, "Tokyo came up {} times", tokyo);
+
+        // Pinned like the uniform picks
+        let mut rng = seeded_rng(2024);
+        let knivsta = (0..1000).position(|_| selector.pick(&mut rng).name == "Knivsta");
+        assert_eq!(knivsta, Some(393));
+    }
+}
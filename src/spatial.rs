@@ -0,0 +1,320 @@
+use std::cmp::Ordering;
+
+use apricity::Coordinate;
+
+use crate::CityData;
+
+/// Mean earth radius, the same as `Coordinate::great_circle_distance` uses.
+pub const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// A latitude/longitude rectangle. When `west > east` the box crosses the antimeridian,
+/// e.g. west 170, east -170 covers the 20 degrees around longitude 180. Longitudes outside
+/// ±180 wrap around, so west 170, east 190 is the same box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl BoundingBox {
+    pub fn new(south: f64, west: f64, north: f64, east: f64) -> BoundingBox {
+        BoundingBox { south, west, north, east }
+    }
+
+    /// Whether the box spans every longitude, e.g. west -180, east 180.
+    fn spans_all_longitudes(&self) -> bool {
+        self.east - self.west >= 360.0
+    }
+
+    pub fn crosses_antimeridian(&self) -> bool {
+        !self.spans_all_longitudes() && wrap_longitude(self.west) > wrap_longitude(self.east)
+    }
+
+    pub fn contains(&self, coordinate: Coordinate) -> bool {
+        let (west, east) = (wrap_longitude(self.west), wrap_longitude(self.east));
+        let longitude = wrap_longitude(coordinate.longitude);
+        let within_longitude = if self.spans_all_longitudes() {
+            true
+        } else if west > east {
+            longitude >= west || longitude <= east
+        } else {
+            west <= longitude && longitude <= east
+        };
+        self.south <= coordinate.latitude && coordinate.latitude <= self.north && within_longitude
+    }
+}
+
+/// Brings a longitude into -180..=180, keeping 180 as it is.
+fn wrap_longitude(longitude: f64) -> f64 {
+    let wrapped = (longitude + 180.0).rem_euclid(360.0) - 180.0;
+    if wrapped == -180.0 && longitude > 0.0 {
+        180.0
+    } else {
+        wrapped
+    }
+}
+
+/// Answers nearest-city, radius and bounding box queries without scanning every city.
+///
+/// Cities are stored in a k-d tree over points on the unit sphere, so distances have no seam
+/// at the antimeridian and no singularity at the poles. The straight-line distance between two
+/// such points grows with the great circle distance, so the tree can be searched with either.
+#[derive(Clone, Debug)]
+pub struct SpatialIndex<'a> {
+    /// In k-d tree order: the middle of every range splits it on axis `depth % 3`.
+    nodes: Vec<([f64; 3], &'a CityData)>,
+    /// Indices into `nodes`, sorted by latitude.
+    by_latitude: Vec<usize>,
+}
+
+impl<'a> SpatialIndex<'a> {
+    pub fn new(cities: impl IntoIterator<Item = &'a CityData>) -> SpatialIndex<'a> {
+        let mut nodes = cities
+            .into_iter()
+            .map(|city| (unit_vector(city.coordinates), city))
+            .collect::<Vec<_>>();
+        build(&mut nodes, 0);
+        let mut by_latitude = (0..nodes.len()).collect::<Vec<_>>();
+        by_latitude.sort_by(|&a, &b| {
+            let latitude = |x: usize| nodes[x].1.coordinates.latitude;
+            latitude(a).total_cmp(&latitude(b))
+        });
+        SpatialIndex { nodes, by_latitude }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The `k` cities closest to `coordinate` with their distance in km, closest first.
+    pub fn nearest(&self, coordinate: Coordinate, k: usize) -> Vec<(&'a CityData, f64)> {
+        let mut found = Vec::with_capacity(k + 1);
+        if k > 0 {
+            self.search_nearest(0, self.nodes.len(), 0, &unit_vector(coordinate), k, &mut found);
+        }
+        found
+            .into_iter()
+            .map(|(distance, index)| (self.nodes[index].1, chord_to_km(distance)))
+            .collect()
+    }
+
+    /// Every city within `radius_km` of `coordinate` with its distance in km, closest first.
+    pub fn within_radius(&self, coordinate: Coordinate, radius_km: f64) -> Vec<(&'a CityData, f64)> {
+        let mut found = Vec::new();
+        let radius = km_to_chord(radius_km);
+        self.search_radius(0, self.nodes.len(), 0, &unit_vector(coordinate), radius, &mut found);
+        found.sort_by(|a, b| a.0.total_cmp(&b.0));
+        found
+            .into_iter()
+            .map(|(distance, index)| (self.nodes[index].1, chord_to_km(distance)))
+            .collect()
+    }
+
+    /// Every city inside `bounds`, ordered by latitude from south to north.
+    pub fn within_bbox(&self, bounds: BoundingBox) -> Vec<&'a CityData> {
+        let latitude = |x: &usize| self.nodes[*x].1.coordinates.latitude;
+        let start = self.by_latitude.partition_point(|x| latitude(x) < bounds.south);
+        let end = self.by_latitude.partition_point(|x| latitude(x) <= bounds.north);
+        self.by_latitude[start..end.max(start)]
+            .iter()
+            .map(|&x| self.nodes[x].1)
+            .filter(|city| bounds.contains(city.coordinates))
+            .collect()
+    }
+
+    /// `found` holds at most `k` (distance, index) pairs, sorted by distance.
+    fn search_nearest(
+        &self,
+        start: usize,
+        end: usize,
+        depth: usize,
+        target: &[f64; 3],
+        k: usize,
+        found: &mut Vec<(f64, usize)>,
+    ) {
+        if start >= end {
+            return;
+        }
+        let middle = (start + end) / 2;
+        let (point, _) = &self.nodes[middle];
+        let distance = chord(point, target);
+        if found.len() < k || distance < found[found.len() - 1].0 {
+            let position = found.partition_point(|x| x.0 <= distance);
+            found.insert(position, (distance, middle));
+            found.truncate(k);
+        }
+
+        let axis = depth % 3;
+        let offset = target[axis] - point[axis];
+        let (near, far) = if offset < 0.0 {
+            ((start, middle), (middle + 1, end))
+        } else {
+            ((middle + 1, end), (start, middle))
+        };
+        self.search_nearest(near.0, near.1, depth + 1, target, k, found);
+        if found.len() < k || offset.abs() < found[found.len() - 1].0 {
+            self.search_nearest(far.0, far.1, depth + 1, target, k, found);
+        }
+    }
+
+    fn search_radius(
+        &self,
+        start: usize,
+        end: usize,
+        depth: usize,
+        target: &[f64; 3],
+        radius: f64,
+        found: &mut Vec<(f64, usize)>,
+    ) {
+        if start >= end {
+            return;
+        }
+        let middle = (start + end) / 2;
+        let (point, _) = &self.nodes[middle];
+        let distance = chord(point, target);
+        if distance <= radius {
+            found.push((distance, middle));
+        }
+
+        let axis = depth % 3;
+        let offset = target[axis] - point[axis];
+        if offset - radius <= 0.0 {
+            self.search_radius(start, middle, depth + 1, target, radius, found);
+        }
+        if offset + radius >= 0.0 {
+            self.search_radius(middle + 1, end, depth + 1, target, radius, found);
+        }
+    }
+}
+
+/// Orders `nodes` so the middle element splits it on axis `depth % 3`, recursively.
+fn build(nodes: &mut [([f64; 3], &CityData)], depth: usize) {
+    if nodes.len() <= 1 {
+        return;
+    }
+    let axis = depth % 3;
+    let middle = nodes.len() / 2;
+    nodes.select_nth_unstable_by(middle, |a, b| a.0[axis].partial_cmp(&b.0[axis]).unwrap_or(Ordering::Equal));
+    let (left, right) = nodes.split_at_mut(middle);
+    build(left, depth + 1);
+    build(&mut right[1..], depth + 1);
+}
+
+fn unit_vector(coordinate: Coordinate) -> [f64; 3] {
+    let latitude = coordinate.latitude.to_radians();
+    let longitude = coordinate.longitude.to_radians();
+    [
+        latitude.cos() * longitude.cos(),
+        latitude.cos() * longitude.sin(),
+        latitude.sin(),
+    ]
+}
+
+fn chord(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum::<f64>().sqrt()
+}
+
+fn chord_to_km(chord: f64) -> f64 {
+    2.0 * EARTH_RADIUS_KM * (chord / 2.0).min(1.0).asin()
+}
+
+/// Distances beyond half the circumference are clamped to the antipode.
+fn km_to_chord(km: f64) -> f64 {
+    let angle = (km / EARTH_RADIUS_KM).clamp(0.0, std::f64::consts::PI);
+    2.0 * (angle / 2.0).sin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn city(name: &str, latitude: f64, longitude: f64) -> CityData {
+        test_support::city(name).coordinates(latitude, longitude).population(1000).build()
+    }
+
+    fn names(cities: &[(&CityData, f64)]) -> Vec<String> {
+        cities.iter().map(|x| x.0.name.clone()).collect()
+    }
+
+    #[test]
+    fn test_antimeridian() {
+        let cities = [
+            city("East", -17.0, 179.9),
+            city("West", -17.0, -179.9),
+            city("Far", -17.0, 170.0),
+        ];
+        let index = SpatialIndex::new(&cities);
+        let nearest = index.nearest(Coordinate { latitude: -17.0, longitude: -179.95 }, 2);
+        assert_eq!(names(&nearest), ["West", "East"]);
+        assert!(nearest[1].1 < 20.0);
+
+        let within = index.within_radius(Coordinate { latitude: -17.0, longitude: 180.0 }, 50.0);
+        assert_eq!(within.len(), 2);
+
+        let bounds = BoundingBox::new(-20.0, 175.0, -10.0, -175.0);
+        let inside = index.within_bbox(bounds);
+        assert_eq!(inside.len(), 2);
+        assert!(inside.iter().all(|x| x.name != "Far"));
+
+        // The same box, with the east edge past 180
+        let bounds = BoundingBox::new(-20.0, 175.0, -10.0, 185.0);
+        assert!(bounds.crosses_antimeridian());
+        assert_eq!(index.within_bbox(bounds).len(), 2);
+        let bounds = BoundingBox::new(-20.0, 165.0, -10.0, 190.0);
+        assert_eq!(index.within_bbox(bounds).len(), 3);
+        let bounds = BoundingBox::new(-20.0, -185.0, -10.0, -175.0);
+        let inside = index.within_bbox(bounds);
+        assert_eq!(inside.len(), 2);
+        assert!(inside.iter().all(|x| x.name != "Far"));
+        assert!(!BoundingBox::new(-20.0, 0.0, -10.0, 180.0).crosses_antimeridian());
+    }
+
+    #[test]
+    fn test_poles() {
+        let cities = [
+            city("A", 89.9, 0.0),
+            city("B", 89.9, 180.0),
+            city("C", 80.0, 90.0),
+        ];
+        let index = SpatialIndex::new(&cities);
+        let within = index.within_radius(Coordinate { latitude: 90.0, longitude: -45.0 }, 20.0);
+        assert_eq!(within.len(), 2);
+        let inside = index.within_bbox(BoundingBox::new(85.0, -180.0, 90.0, 180.0));
+        assert_eq!(inside.len(), 2);
+    }
+
+    #[test]
+    fn test_matches_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let cities = (0..500)
+            .map(|i| city(&i.to_string(), rng.gen_range(-90.0..90.0), rng.gen_range(-180.0..180.0)))
+            .collect::<Vec<_>>();
+        let index = SpatialIndex::new(&cities);
+        for _ in 0..20 {
+            let target = Coordinate {
+                latitude: rng.gen_range(-90.0..90.0),
+                longitude: rng.gen_range(-180.0..180.0),
+            };
+            let mut expected = cities
+                .iter()
+                .map(|x| (x, chord_to_km(chord(&unit_vector(x.coordinates), &unit_vector(target)))))
+                .collect::<Vec<_>>();
+            expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+            let nearest = index.nearest(target, 5);
+            assert_eq!(names(&nearest), names(&expected[..5]));
+
+            let within = index.within_radius(target, 1500.0);
+            let count = expected.iter().filter(|x| x.1 <= 1500.0).count();
+            assert_eq!(names(&within), names(&expected[..count]));
+        }
+    }
+}
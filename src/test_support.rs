@@ -0,0 +1,78 @@
+//! Cities for unit tests, with only the fields a test cares about filled in.
+
+use apricity::Coordinate;
+
+use crate::{CityData, GeonameId};
+
+/// Builds a `CityData` for a test, e.g.
+/// `city("Uppsala").country("SE").population(133_117).build()`.
+pub(crate) struct CityBuilder(CityData);
+
+/// A populated place at 0°, 0° with no people, no elevation and geoname id 1.
+pub(crate) fn city(name: &str) -> CityBuilder {
+    let city = serde_json::from_value(serde_json::json!({
+        "coordinates": [0.0, 0.0],
+        "feature_code": "PPL",
+        "population": 0,
+        "dem": 0,
+        "geoname_id": "1",
+        "name": name,
+        "feature_class": "P",
+        "country_code": "FJ",
+        "timezone": "UTC",
+        "modification_date": "2020-01-01"
+    }))
+    .unwrap();
+    CityBuilder(city)
+}
+
+impl CityBuilder {
+    pub(crate) fn coordinates(mut self, latitude: f64, longitude: f64) -> Self {
+        self.0.coordinates = Coordinate { latitude, longitude };
+        self
+    }
+
+    pub(crate) fn geoname_id(mut self, geoname_id: u64) -> Self {
+        self.0.geoname_id = GeonameId(geoname_id);
+        self
+    }
+
+    pub(crate) fn country(mut self, country_code: &str) -> Self {
+        self.0.country_code = Some(country_code.parse().unwrap());
+        self
+    }
+
+    pub(crate) fn admin1(mut self, admin1_code: &str) -> Self {
+        self.0.admin1_code = Some(admin1_code.to_string());
+        self
+    }
+
+    pub(crate) fn feature_code(mut self, feature_code: &str) -> Self {
+        self.0.feature_code = Some(feature_code.parse().unwrap());
+        self
+    }
+
+    pub(crate) fn population(mut self, population: i64) -> Self {
+        self.0.population = population;
+        self
+    }
+
+    pub(crate) fn dem(mut self, dem: i64) -> Self {
+        self.0.dem = dem;
+        self
+    }
+
+    pub(crate) fn timezone(mut self, timezone: &str) -> Self {
+        self.0.timezone = timezone.to_string();
+        self
+    }
+
+    pub(crate) fn modification_date(mut self, date: &str) -> Self {
+        self.0.modification_date = date.parse().unwrap();
+        self
+    }
+
+    pub(crate) fn build(self) -> CityData {
+        self.0
+    }
+}
@@ -0,0 +1,632 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Error from parsing one of the typed `CityData` fields from text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseFieldError {
+    pub expected: &'static str,
+    pub value: String,
+}
+
+impl ParseFieldError {
+    fn new(expected: &'static str, value: &str) -> ParseFieldError {
+        ParseFieldError {
+            expected,
+            value: value.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseFieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} is not a valid {}", self.value, self.expected)
+    }
+}
+
+impl std::error::Error for ParseFieldError {}
+
+// The typed fields are written as text, the way they appear in the JSON export.
+// Binary formats such as the cache go through the same representation.
+macro_rules! serde_via_str {
+    ($type:ty) => {
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let text = String::deserialize(deserializer)?;
+                text.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+/// Serde helper for the codes GeoNames leaves blank on some places, such as the country of an
+/// undersea feature. A blank or missing value is `None`.
+pub(crate) mod blank_as_none {
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T: Display, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.as_ref().map(|x| x.to_string()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(deserializer)?.as_deref() {
+            None | Some("") => Ok(None),
+            Some(x) => x.parse().map(Some).map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// GeoNames id of a place, e.g. 2918632 for Göttingen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GeonameId(pub u64);
+
+impl fmt::Display for GeonameId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for GeonameId {
+    type Err = ParseFieldError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .map(GeonameId)
+            .map_err(|_| ParseFieldError::new("geoname id", s))
+    }
+}
+
+// The JSON export has the id as a string, but the cache can store the number itself.
+impl Serialize for GeonameId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_u64(self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for GeonameId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let text = String::deserialize(deserializer)?;
+            text.parse().map_err(serde::de::Error::custom)
+        } else {
+            u64::deserialize(deserializer).map(GeonameId)
+        }
+    }
+}
+
+/// ISO 3166-1 alpha-2 country code, always upper case.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CountryCode([u8; 2]);
+
+impl CountryCode {
+    pub fn as_str(&self) -> &str {
+        // Only ever holds ASCII letters
+        std::str::from_utf8(&self.0).unwrap()
+    }
+}
+
+impl fmt::Debug for CountryCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CountryCode({})", self.as_str())
+    }
+}
+
+impl fmt::Display for CountryCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Accepts either case, so "se" and "SE" are the same country.
+impl FromStr for CountryCode {
+    type Err = ParseFieldError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            [a, b] if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => {
+                Ok(CountryCode([a.to_ascii_uppercase(), b.to_ascii_uppercase()]))
+            }
+            _ => Err(ParseFieldError::new("country code", s)),
+        }
+    }
+}
+
+serde_via_str!(CountryCode);
+
+/// GeoNames feature class, the first level of what kind of place a record is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FeatureClass {
+    /// Country, state, region
+    A,
+    /// Stream, lake
+    H,
+    /// Park, area
+    L,
+    /// City, village
+    P,
+    /// Road, railroad
+    R,
+    /// Spot, building, farm
+    S,
+    /// Mountain, hill, rock
+    T,
+    /// Undersea
+    U,
+    /// Forest, heath
+    V,
+}
+
+impl FeatureClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeatureClass::A => "A",
+            FeatureClass::H => "H",
+            FeatureClass::L => "L",
+            FeatureClass::P => "P",
+            FeatureClass::R => "R",
+            FeatureClass::S => "S",
+            FeatureClass::T => "T",
+            FeatureClass::U => "U",
+            FeatureClass::V => "V",
+        }
+    }
+}
+
+impl fmt::Display for FeatureClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for FeatureClass {
+    type Err = ParseFieldError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "A" => Ok(FeatureClass::A),
+            "H" => Ok(FeatureClass::H),
+            "L" => Ok(FeatureClass::L),
+            "P" => Ok(FeatureClass::P),
+            "R" => Ok(FeatureClass::R),
+            "S" => Ok(FeatureClass::S),
+            "T" => Ok(FeatureClass::T),
+            "U" => Ok(FeatureClass::U),
+            "V" => Ok(FeatureClass::V),
+            _ => Err(ParseFieldError::new("feature class", s)),
+        }
+    }
+}
+
+serde_via_str!(FeatureClass);
+
+/// GeoNames feature code. The populated place codes found in the city exports have their
+/// own variants; anything else is kept as `Other`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FeatureCode {
+    /// Populated place
+    Ppl,
+    /// Seat of a first-order administrative division
+    Ppla,
+    /// Seat of a second-order administrative division
+    Ppla2,
+    /// Seat of a third-order administrative division
+    Ppla3,
+    /// Seat of a fourth-order administrative division
+    Ppla4,
+    /// Seat of a fifth-order administrative division
+    Ppla5,
+    /// Capital of a political entity
+    Pplc,
+    /// Historical capital of a political entity
+    Pplch,
+    /// Farm village
+    Pplf,
+    /// Seat of government of a political entity
+    Pplg,
+    /// Historical populated place
+    Pplh,
+    /// Populated locality
+    Ppll,
+    /// Abandoned populated place
+    Pplq,
+    /// Religious populated place
+    Pplr,
+    /// Populated places
+    Ppls,
+    /// Destroyed populated place
+    Pplw,
+    /// Section of populated place
+    Pplx,
+    /// Israeli settlement
+    Stlmt,
+    Other(String),
+}
+
+impl FeatureCode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            FeatureCode::Ppl => "PPL",
+            FeatureCode::Ppla => "PPLA",
+            FeatureCode::Ppla2 => "PPLA2",
+            FeatureCode::Ppla3 => "PPLA3",
+            FeatureCode::Ppla4 => "PPLA4",
+            FeatureCode::Ppla5 => "PPLA5",
+            FeatureCode::Pplc => "PPLC",
+            FeatureCode::Pplch => "PPLCH",
+            FeatureCode::Pplf => "PPLF",
+            FeatureCode::Pplg => "PPLG",
+            FeatureCode::Pplh => "PPLH",
+            FeatureCode::Ppll => "PPLL",
+            FeatureCode::Pplq => "PPLQ",
+            FeatureCode::Pplr => "PPLR",
+            FeatureCode::Ppls => "PPLS",
+            FeatureCode::Pplw => "PPLW",
+            FeatureCode::Pplx => "PPLX",
+            FeatureCode::Stlmt => "STLMT",
+            FeatureCode::Other(x) => x,
+        }
+    }
+
+    /// Whether this is the capital of a country.
+    pub fn is_capital(&self) -> bool {
+        *self == FeatureCode::Pplc
+    }
+
+    /// Whether this is the seat of an administrative division (or of the country itself).
+    pub fn is_administrative_seat(&self) -> bool {
+        matches!(
+            self,
+            FeatureCode::Pplc
+                | FeatureCode::Pplg
+                | FeatureCode::Ppla
+                | FeatureCode::Ppla2
+                | FeatureCode::Ppla3
+                | FeatureCode::Ppla4
+                | FeatureCode::Ppla5
+        )
+    }
+}
+
+impl fmt::Display for FeatureCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for FeatureCode {
+    type Err = ParseFieldError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = match s {
+            "PPL" => FeatureCode::Ppl,
+            "PPLA" => FeatureCode::Ppla,
+            "PPLA2" => FeatureCode::Ppla2,
+            "PPLA3" => FeatureCode::Ppla3,
+            "PPLA4" => FeatureCode::Ppla4,
+            "PPLA5" => FeatureCode::Ppla5,
+            "PPLC" => FeatureCode::Pplc,
+            "PPLCH" => FeatureCode::Pplch,
+            "PPLF" => FeatureCode::Pplf,
+            "PPLG" => FeatureCode::Pplg,
+            "PPLH" => FeatureCode::Pplh,
+            "PPLL" => FeatureCode::Ppll,
+            "PPLQ" => FeatureCode::Pplq,
+            "PPLR" => FeatureCode::Pplr,
+            "PPLS" => FeatureCode::Ppls,
+            "PPLW" => FeatureCode::Pplw,
+            "PPLX" => FeatureCode::Pplx,
+            "STLMT" => FeatureCode::Stlmt,
+            _ if !s.is_empty() && s.chars().all(|x| x.is_ascii_uppercase() || x.is_ascii_digit()) => {
+                FeatureCode::Other(s.to_string())
+            }
+            _ => return Err(ParseFieldError::new("feature code", s)),
+        };
+        Ok(code)
+    }
+}
+
+serde_via_str!(FeatureCode);
+
+/// A calendar date, like the `modification_date` of a record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    year: i32,
+    month: u8,
+    day: u8,
+}
+
+impl Date {
+    pub fn new(year: i32, month: u8, day: u8) -> Option<Date> {
+        if (1..=12).contains(&month) && day >= 1 && day <= days_in_month(year, month) {
+            Some(Date { year, month, day })
+        } else {
+            None
+        }
+    }
+
+    pub fn year(&self) -> i32 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    /// Days since 1970-01-01.
+    pub fn days_since_epoch(&self) -> i64 {
+        // Howard Hinnant's days_from_civil
+        let year = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146097 + day_of_era - 719468
+    }
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Parses `YYYY-MM-DD`.
+impl FromStr for Date {
+    type Err = ParseFieldError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseFieldError::new("date", s);
+        let bytes = s.as_bytes();
+        if !s.is_ascii() || bytes.len() != 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+            return Err(error());
+        }
+        let year = parse_digits(&s[0..4]).ok_or_else(error)?;
+        let month = parse_digits(&s[5..7]).ok_or_else(error)?;
+        let day = parse_digits(&s[8..10]).ok_or_else(error)?;
+        Date::new(year as i32, month as u8, day as u8).ok_or_else(error)
+    }
+}
+
+serde_via_str!(Date);
+
+fn parse_digits(s: &str) -> Option<u32> {
+    if !s.is_empty() && s.bytes().all(|x| x.is_ascii_digit()) {
+        s.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// An RFC 3339 timestamp with its UTC offset, like the `record_timestamp` of a record.
+///
+/// Timestamps compare by the instant they describe, so the same moment written with
+/// different offsets is equal.
+#[derive(Clone, Copy, Debug)]
+pub struct Timestamp {
+    date: Date,
+    hour: u8,
+    minute: u8,
+    second: u8,
+    nanosecond: u32,
+    offset_minutes: i16,
+}
+
+impl Timestamp {
+    /// Midnight UTC at the start of `date`.
+    pub fn start_of_day(date: Date) -> Timestamp {
+        Timestamp {
+            date,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            nanosecond: 0,
+            offset_minutes: 0,
+        }
+    }
+
+    /// The local date, in the timestamp's own offset.
+    pub fn date(&self) -> Date {
+        self.date
+    }
+
+    pub fn offset_minutes(&self) -> i16 {
+        self.offset_minutes
+    }
+
+    /// Seconds since 1970-01-01T00:00:00Z.
+    pub fn unix_seconds(&self) -> i64 {
+        self.date.days_since_epoch() * 86400
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64
+            - self.offset_minutes as i64 * 60
+    }
+}
+
+impl PartialEq for Timestamp {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Timestamp {}
+
+impl PartialOrd for Timestamp {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timestamp {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.unix_seconds(), self.nanosecond).cmp(&(other.unix_seconds(), other.nanosecond))
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}T{:02}:{:02}:{:02}", self.date, self.hour, self.minute, self.second)?;
+        if self.nanosecond != 0 {
+            let fraction = format!("{:09}", self.nanosecond);
+            write!(f, ".{}", fraction.trim_end_matches('0'))?;
+        }
+        if self.offset_minutes == 0 {
+            write!(f, "Z")
+        } else {
+            let sign = if self.offset_minutes < 0 { '-' } else { '+' };
+            let offset = self.offset_minutes.unsigned_abs();
+            write!(f, "{}{:02}:{:02}", sign, offset / 60, offset % 60)
+        }
+    }
+}
+
+/// Parses `YYYY-MM-DDTHH:MM:SS[.fraction](Z|+HH:MM|-HH:MM)`.
+impl FromStr for Timestamp {
+    type Err = ParseFieldError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseFieldError::new("timestamp", s);
+        if !s.is_ascii() || s.len() < 20 || !matches!(s.as_bytes()[10], b'T' | b't' | b' ') {
+            return Err(error());
+        }
+        let date = s[..10].parse::<Date>().map_err(|_| error())?;
+
+        let time = &s[11..];
+        let bytes = time.as_bytes();
+        if bytes[2] != b':' || bytes[5] != b':' {
+            return Err(error());
+        }
+        let hour = parse_digits(&time[0..2]).filter(|x| *x < 24).ok_or_else(error)?;
+        let minute = parse_digits(&time[3..5]).filter(|x| *x < 60).ok_or_else(error)?;
+        // 60 allows for leap seconds
+        let second = parse_digits(&time[6..8]).filter(|x| *x <= 60).ok_or_else(error)?;
+
+        let mut rest = &time[8..];
+        let mut nanosecond = 0;
+        if let Some(fraction) = rest.strip_prefix('.') {
+            let digits = fraction.bytes().take_while(|x| x.is_ascii_digit()).count();
+            if digits == 0 {
+                return Err(error());
+            }
+            // Only nanosecond precision is kept
+            let kept = &fraction[..digits.min(9)];
+            nanosecond = parse_digits(kept).ok_or_else(error)? * 10u32.pow(9 - kept.len() as u32);
+            rest = &fraction[digits..];
+        }
+
+        let offset_minutes = match rest {
+            "Z" | "z" => 0,
+            _ if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+                let sign = match rest.as_bytes()[0] {
+                    b'+' => 1,
+                    b'-' => -1,
+                    _ => return Err(error()),
+                };
+                let hours = parse_digits(&rest[1..3]).filter(|x| *x < 24).ok_or_else(error)?;
+                let minutes = parse_digits(&rest[4..6]).filter(|x| *x < 60).ok_or_else(error)?;
+                sign * (hours * 60 + minutes) as i16
+            }
+            _ => return Err(error()),
+        };
+
+        Ok(Timestamp {
+            date,
+            hour: hour as u8,
+            minute: minute as u8,
+            second: second as u8,
+            nanosecond,
+            offset_minutes,
+        })
+    }
+}
+
+serde_via_str!(Timestamp);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_country_code() {
+        let sweden = "se".parse::<CountryCode>().unwrap();
+        assert_eq!(sweden, "SE".parse().unwrap());
+        assert_eq!(sweden.as_str(), "SE");
+        assert!("SWE".parse::<CountryCode>().is_err());
+        assert!("S1".parse::<CountryCode>().is_err());
+    }
+
+    #[test]
+    fn test_feature_code() {
+        assert_eq!("PPLC".parse::<FeatureCode>().unwrap(), FeatureCode::Pplc);
+        assert!(FeatureCode::Pplc.is_capital());
+        assert_eq!("ADM1".parse::<FeatureCode>().unwrap(), FeatureCode::Other("ADM1".to_string()));
+        assert!("ppl".parse::<FeatureCode>().is_err());
+        assert!("X".parse::<FeatureClass>().is_err());
+    }
+
+    #[test]
+    fn test_date() {
+        let date = "2019-09-05".parse::<Date>().unwrap();
+        assert_eq!((date.year(), date.month(), date.day()), (2019, 9, 5));
+        assert_eq!(date.to_string(), "2019-09-05");
+        assert_eq!("1970-01-01".parse::<Date>().unwrap().days_since_epoch(), 0);
+        assert!("2019-02-29".parse::<Date>().is_err());
+        assert!("2020-02-29".parse::<Date>().is_ok());
+        assert!("2019-9-05".parse::<Date>().is_err());
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let text = "2022-10-10T08:00:01.602+02:00";
+        let timestamp = text.parse::<Timestamp>().unwrap();
+        assert_eq!(timestamp.to_string(), text);
+        assert_eq!(timestamp.offset_minutes(), 120);
+        assert_eq!(timestamp.unix_seconds(), 1665381601);
+        let utc = "2022-10-10T06:00:01.602Z".parse::<Timestamp>().unwrap();
+        assert_eq!(timestamp, utc);
+        assert!("2022-10-10T08:00:01".parse::<Timestamp>().is_err());
+        assert!("2022-10-10T25:00:01Z".parse::<Timestamp>().is_err());
+    }
+
+    #[test]
+    fn test_geoname_id_serde() {
+        let id = serde_json::from_str::<GeonameId>(r#""2918632""#).unwrap();
+        assert_eq!(id, GeonameId(2918632));
+        assert_eq!(serde_json::to_string(&id).unwrap(), r#""2918632""#);
+        let bytes = bincode::serialize(&id).unwrap();
+        assert_eq!(bincode::deserialize::<GeonameId>(&bytes).unwrap(), id);
+    }
+}